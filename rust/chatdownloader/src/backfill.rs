@@ -1,64 +1,65 @@
 /*
-A function to backfill given video IDs
+A function to backfill past VODs
 */
 
-use std::env;
 use std::sync::Arc;
 
-use elo::_types::clptypes::Message;
-use log::info;
+use log::{info, warn};
 use twitch_utils::seventvclient::SevenTVClient;
 
-use crate::chatlogprocessor::ChatLogProcessor;
+use crate::chatcache::chat_cache;
+use crate::cli::{BackfillArgs, Settings};
+use crate::process::process_vod;
 use crate::twitchdownloaderproxy::TwitchChatDownloader;
-use crate::{discorddownloaderproxy, CHANNEL_ID};
-use twitch_utils::TwitchAPIWrapper;
+use twitch_utils::datasource::ChatDataSource;
 
-pub async fn backfill() {
-    let twitch = TwitchAPIWrapper::new().await.unwrap();
-    let seventv_client = Arc::new(SevenTVClient::new().await);
-    let video_ids = twitch
-        .get_latest_vod_ids(elo::_constants::VED_CH_ID.to_string(), 5)
-        .await;
-    let mut downloader = TwitchChatDownloader::new();
+/// VODs backfilled when no count or date is given
+const DEFAULT_BACKFILL_COUNT: usize = 5;
 
-    for video_id in video_ids.iter() {
-        info!("Backfilling for video ID: {}", video_id);
-        // let chat_log = downloader.download_chat(video_id).await.unwrap();
-        let chat_log = downloader
-            .download_chat(video_id)
-            .await
-            .expect("Could not download chat log: {e:?}")
-            .comments
-            .into_iter()
-            .map(Message::Twitch);
+/// Most VODs looked through when backfilling since a date
+const MAX_BACKFILL_VODS: usize = 100;
 
-        let discord_messages = match env::var("CHAT_DISCORD_TOKEN") {
-            Ok(token) => {
-                let (start_time, end_time) = twitch.get_vod_times(video_id.to_string()).await;
-                discorddownloaderproxy::DiscordChatDownloader::new()
-                    .download_chat(
-                        start_time.into(),
-                        end_time.into(),
-                        CHANNEL_ID,
-                        token.as_str(),
-                    )
-                    .await
-                    .expect("Failed to download Discord chat")
-                    .messages
-            }
-            _ => {
-                vec![]
+pub async fn backfill(
+    source: &dyn ChatDataSource,
+    seventv_client: Arc<SevenTVClient>,
+    args: &BackfillArgs,
+    settings: &Settings,
+) {
+    let video_ids = match args.since {
+        Some(since) => {
+            let mut video_ids = Vec::new();
+            for video_id in source
+                .get_latest_vod_ids(elo::_constants::VED_CH_ID.to_string(), MAX_BACKFILL_VODS)
+                .await
+            {
+                match source.get_vod_info(video_id.clone()).await {
+                    Ok(stream) if stream.start.date_naive() >= since => video_ids.push(video_id),
+                    Ok(_) => {}
+                    Err(e) => warn!("Skipping VOD {}: {}", video_id, e),
+                }
             }
+            video_ids
         }
-        .into_iter()
-        .map(Message::Discord);
-
-        let user_performances = ChatLogProcessor::new(&twitch, seventv_client.clone())
-            .await
-            .process_from_messages(chat_log.chain(discord_messages))
-            .await;
+        None => {
+            source
+                .get_latest_vod_ids(
+                    elo::_constants::VED_CH_ID.to_string(),
+                    args.count.unwrap_or(DEFAULT_BACKFILL_COUNT),
+                )
+                .await
+        }
+    };
+    let mut downloader = TwitchChatDownloader::new(chat_cache(settings));
 
-        ChatLogProcessor::export_to_leaderboards(user_performances).await;
+    for video_id in video_ids.iter() {
+        info!("Backfilling for video ID: {}", video_id);
+        process_vod(
+            source,
+            seventv_client.clone(),
+            &mut downloader,
+            video_id,
+            settings,
+        )
+        .await;
     }
 }
@@ -0,0 +1,208 @@
+/*
+Keeps downloaded chat logs on disk, so processing a stream again doesn't
+download its chat again
+*/
+
+use std::error::Error;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use elo::leaderboards::store::{filesystem::FileStore, storetrait::LeaderboardStore};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use log::warn;
+use sha2::{Digest, Sha256};
+
+use crate::cli::Settings;
+
+const DEFAULT_CACHE_DIRECTORY: &str = "chat-cache";
+
+/// How long after a VOD or Discord time range ends its chat is cached.
+/// Twitch only updates the length of a VOD that is still live now and
+/// then, so a VOD that seems to have just ended may still be going.
+const CACHE_AFTER: TimeDelta = TimeDelta::hours(1);
+
+/// Raw chat logs, as downloaded
+///
+/// Each chat log is saved gzipped under `objects/`, named after the SHA-256
+/// of its contents, and the file of its VOD or Discord time range names
+/// that object. A cached chat log is only used if its contents still match
+/// the hash, otherwise it is downloaded again.
+#[derive(Clone)]
+pub struct ChatCache {
+    /// Writes files atomically, so an interrupted write never leaves half
+    /// of one
+    store: FileStore,
+    /// Ignores the cached chat logs, but still caches the downloaded ones
+    refresh: bool,
+}
+
+/// The chat cache in `--cache-dir`, or in `chat-cache` if it isn't set
+pub fn chat_cache(settings: &Settings) -> ChatCache {
+    let directory = settings
+        .cache_dir
+        .clone()
+        .unwrap_or(DEFAULT_CACHE_DIRECTORY.into());
+    ChatCache::new(directory, settings.refresh)
+}
+
+fn object_key(hash: &str) -> String {
+    format!("objects/{}.json.gz", hash)
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Whether the chat of a VOD or time range ending at `end` is complete,
+/// and can be cached
+pub fn has_ended(end: DateTime<Utc>) -> bool {
+    Utc::now() - end >= CACHE_AFTER
+}
+
+impl ChatCache {
+    pub fn new(directory: PathBuf, refresh: bool) -> Self {
+        Self {
+            store: FileStore::new(directory),
+            refresh,
+        }
+    }
+
+    /// The key of a VOD's chat log
+    pub fn vod_key(vod_id: &str) -> String {
+        format!("twitch/{}", vod_id)
+    }
+
+    /// The key of a Discord channel's chat between two times
+    pub fn discord_key(channel_id: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+        format!(
+            "discord/{}/{}-{}",
+            channel_id,
+            start.timestamp(),
+            end.timestamp()
+        )
+    }
+
+    /// The cached chat log, unless it isn't cached, doesn't match its hash
+    /// or `--refresh` is set
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        if self.refresh {
+            return None;
+        }
+
+        match self.read(key) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Ignoring the cached chat log {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let Some(hash) = self.store.read(key)? else {
+            return Ok(None);
+        };
+        let hash = String::from_utf8(hash)?;
+        let compressed = self
+            .store
+            .read(&object_key(&hash))?
+            .ok_or("its object is missing")?;
+
+        let mut data = Vec::new();
+        GzDecoder::new(compressed.as_slice()).read_to_end(&mut data)?;
+        if sha256(&data) != hash {
+            return Err("its contents don't match its hash".into());
+        }
+        Ok(Some(data))
+    }
+
+    /// Caches a chat log
+    ///
+    /// The object is written before the key naming it, so an interrupted
+    /// write never leaves a key without its object.
+    pub fn put(&self, key: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let hash = sha256(data);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        self.store.write(&object_key(&hash), &encoder.finish()?)?;
+        self.store.write(key, hash.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::cli::Cli;
+
+    #[test]
+    fn a_cached_chat_log_is_only_used_while_it_matches_its_hash() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = ChatCache::new(directory.path().to_path_buf(), false);
+        let key = ChatCache::vod_key("1");
+        assert_eq!(cache.get(&key), None);
+
+        cache.put(&key, b"{\"comments\": []}").unwrap();
+        assert_eq!(cache.get(&key).unwrap(), b"{\"comments\": []}");
+
+        let hash = fs::read_to_string(directory.path().join(&key)).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"truncated").unwrap();
+        fs::write(
+            directory.path().join(object_key(&hash)),
+            encoder.finish().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(cache.get(&key), None);
+    }
+
+    #[test]
+    fn a_corrupt_object_is_downloaded_again() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = ChatCache::new(directory.path().to_path_buf(), false);
+        let key = ChatCache::vod_key("1");
+        cache.put(&key, b"{\"comments\": []}").unwrap();
+        let hash = fs::read_to_string(directory.path().join(&key)).unwrap();
+        let object = directory.path().join(object_key(&hash));
+
+        fs::write(&object, b"not gzip").unwrap();
+        assert_eq!(cache.get(&key), None);
+
+        fs::remove_file(&object).unwrap();
+        assert_eq!(cache.get(&key), None);
+
+        cache.put(&key, b"{\"comments\": []}").unwrap();
+        assert_eq!(cache.get(&key).unwrap(), b"{\"comments\": []}");
+    }
+
+    #[test]
+    fn refreshing_ignores_the_cache_but_still_fills_it() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = ChatCache::new(directory.path().to_path_buf(), false);
+        let cli = Cli::parse_from([
+            "chatdownloader".as_ref(),
+            "--cache-dir".as_ref(),
+            directory.path().as_os_str(),
+            "--refresh".as_ref(),
+        ]);
+        let refreshing = chat_cache(&cli.settings);
+        let key = ChatCache::vod_key("1");
+        cache.put(&key, b"old").unwrap();
+
+        assert_eq!(refreshing.get(&key), None);
+
+        refreshing.put(&key, b"new").unwrap();
+        assert_eq!(refreshing.get(&key), None);
+        assert_eq!(cache.get(&key).unwrap(), b"new");
+    }
+
+    #[test]
+    fn only_chat_that_ended_a_while_ago_is_cached() {
+        assert!(has_ended(Utc::now() - TimeDelta::days(1)));
+        assert!(!has_ended(Utc::now() - TimeDelta::minutes(5)));
+    }
+}
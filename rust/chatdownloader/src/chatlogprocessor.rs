@@ -1,15 +1,32 @@
-use elo::{MessageProcessorSetup, MessageProcessorRunning};
 use elo::_types::clptypes::{Message, UserChatPerformance};
+#[cfg(feature = "sqlite")]
+use elo::database::Database;
+use elo::leaderboards::definition::{LeaderboardsConfig, DEFAULT_LEADERBOARDS};
+use elo::leaderboards::export::ExportFormat;
+use elo::leaderboards::header::RunInfo;
+use elo::leaderboards::season::Season;
+use elo::leaderboards::store::{
+    checked::checksum, filesystem::FileStore, overlay::OverlayStore, storetrait::LeaderboardStore,
+};
 use elo::leaderboards::LeaderboardProcessor;
+use elo::scoring::ScoringConfig;
+use elo::{MessageProcessorRunning, MessageProcessorSetup};
 use log::{debug, info};
-use std::fs;
-use std::sync::Arc;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 use std::time::Instant;
+use std::{env, fs};
+use twitch_utils::datasource::ChatDataSource;
+#[cfg(feature = "sqlite")]
+use twitch_utils::datasource::VodInfo;
 use twitch_utils::seventvclient::SevenTVClient;
-use twitch_utils::TwitchAPIWrapper;
 
 use twitch_utils::twitchtypes::ChatLog;
 
+use crate::cli::Settings;
+use crate::inspect::report_dry_run;
+
 pub struct ChatLogProcessor {
     /*
     Processes the chat logs.
@@ -22,9 +39,99 @@ pub struct ChatLogProcessor {
     message_processor: MessageProcessorRunning,
 }
 
+/// The changes of a dry run, kept for the whole run so that each stream
+/// builds on the ones before it
+static DRY_RUN_STORE: OnceLock<OverlayStore> = OnceLock::new();
+
+/// Reads the scoring config from `--scoring-config` if it is set,
+/// otherwise the default scoring is used
+fn scoring_config(settings: &Settings) -> ScoringConfig {
+    match &settings.scoring_config {
+        Some(path) => ScoringConfig::from_file(&path.to_string_lossy())
+            .expect("Could not load scoring config"),
+        None => ScoringConfig::default(),
+    }
+}
+
+/// SHA-256 of the scoring config and the leaderboard definitions in use.
+/// The default scoring config has no file, so it hashes as empty; the
+/// tool version in the leaderboard header tells those apart.
+fn config_hash(settings: &Settings) -> String {
+    let scoring = match &settings.scoring_config {
+        Some(path) => fs::read_to_string(path).expect("Could not read scoring config"),
+        None => String::new(),
+    };
+    let leaderboards = match &settings.config {
+        Some(path) => fs::read_to_string(path).expect("Could not read leaderboard definitions"),
+        None => DEFAULT_LEADERBOARDS.to_string(),
+    };
+    checksum(format!("{}\n{}", scoring, leaderboards).as_bytes())
+}
+
+/// Where the leaderboards are saved, from `--output-dir` if it is set,
+/// otherwise the working directory
+fn output_directory(settings: &Settings) -> PathBuf {
+    settings.output_dir.clone().unwrap_or_default()
+}
+
+/// Reads the season from `SEASON` if it is set, otherwise from the file
+/// in `SEASON_FILE` (the repository's `season.txt` by default). Without
+/// a season, leaderboards are saved in the output directory itself.
+pub fn season(settings: &Settings) -> Option<Season> {
+    let season = match env::var("SEASON") {
+        Ok(id) => Season::new(&id).expect("Invalid SEASON"),
+        Err(_) => {
+            let path = env::var("SEASON_FILE").unwrap_or("../season.txt".to_string());
+            if !std::path::Path::new(&path).exists() {
+                return None;
+            }
+            Season::from_file(&path).expect("Could not read the season file")
+        }
+    };
+    Some(season.in_directory(&output_directory(settings)))
+}
+
+/// Where the leaderboards are kept: the season's directory if there is a
+/// season, otherwise the output directory
+pub fn leaderboard_store(settings: &Settings) -> Arc<dyn LeaderboardStore> {
+    match season(settings) {
+        Some(season) => Arc::new(season.store()),
+        None => Arc::new(FileStore::new(output_directory(settings))),
+    }
+}
+
+/// Records the stream and the leaderboards after it in the SQLite
+/// database in `DATABASE`, if it is set
+#[cfg(feature = "sqlite")]
+fn record_in_database(
+    stream: &VodInfo,
+    performances: &[UserChatPerformance],
+    store: &dyn LeaderboardStore,
+    leaderboards: &[String],
+) {
+    let Ok(path) = env::var("DATABASE") else {
+        return;
+    };
+    let mut database = Database::open(&path).expect("Could not open the database");
+    database
+        .record_stream(stream, performances)
+        .expect("Could not record the stream in the database");
+    database
+        .record_leaderboards(store, leaderboards, stream)
+        .expect("Could not record the leaderboards in the database");
+    info!("Recorded stream {} in {}", stream.id, path);
+}
+
 impl ChatLogProcessor {
-    pub async fn new(twitch: &TwitchAPIWrapper, seventv_client: Arc<SevenTVClient>) -> Self {
-        let message_processor = MessageProcessorSetup::new(twitch, seventv_client)
+    pub async fn new(
+        data_source: &dyn ChatDataSource,
+        seventv_client: Arc<SevenTVClient>,
+        settings: &Settings,
+    ) -> Self {
+        let message_processor = MessageProcessorSetup::builder(seventv_client)
+            .data_source(data_source)
+            .scoring(scoring_config(settings))
+            .build()
             .await
             .start()
             .await;
@@ -32,9 +139,10 @@ impl ChatLogProcessor {
         Self { message_processor }
     }
 
-    pub(crate) fn parse_to_log_struct(&self, chat_log_path: String) -> ChatLog {
-        let chat_log_str = fs::read_to_string(chat_log_path).unwrap();
-        serde_json::from_str(&chat_log_str).unwrap()
+    /// Reads a chat log downloaded with TwitchDownloader
+    pub fn parse_to_log_struct(chat_log_path: &Path) -> Result<ChatLog, Box<dyn Error>> {
+        let chat_log_str = fs::read_to_string(chat_log_path)?;
+        Ok(serde_json::from_str(&chat_log_str)?)
     }
 
     pub async fn process_from_messages<Iter: Iterator<Item = Message>>(
@@ -46,30 +154,99 @@ impl ChatLogProcessor {
 
         for message in messages {
             self.message_processor
-                .process_message(message.clone())
-                .await;
+                .process_message(message)
+                .await
+                .expect("Message processors stopped before the end of the chat log");
         }
 
         let performances = self.message_processor.finish().await;
 
         info!("Chat log processing took: {:#?}", start_time.elapsed());
-        performances.into_values().collect()
+        performances
     }
 
-    pub async fn process_from_log_object(self, chat_log: ChatLog) -> Vec<UserChatPerformance> {
-        self.process_from_messages(chat_log.comments.into_iter().map(Message::from))
-            .await
-    }
+    /// A function to export the user performances to the leaderboards and save them
+    ///
+    /// The leaderboards are read from `--config` if it is set, otherwise
+    /// the default leaderboards are used. Leaderboards without a K-factor
+    /// use the one from the scoring config. Setting
+    /// `EXPLAIN_LEADERBOARDS=1` saves an explanation for every leaderboard,
+    /// and `EXPORT_FORMATS` (like `json,csv,pages`) exports every
+    /// leaderboard in those formats too.
+    /// The leaderboards are saved in `leaderboard_store`, along with a
+    /// snapshot of them after the stream. Their header records the run,
+    /// the season and the hash of the configs. On a dry run, nothing is
+    /// saved and the leaderboards so far are printed instead.
+    pub async fn export_to_leaderboards(
+        performances: Vec<UserChatPerformance>,
+        run: RunInfo,
+        settings: &Settings,
+    ) {
+        let store = leaderboard_store(settings);
+        if settings.dry_run {
+            let overlay = DRY_RUN_STORE
+                .get_or_init(|| OverlayStore::new(store))
+                .clone();
+            let leaderboards =
+                Self::update_leaderboards(performances, run, settings, Arc::new(overlay.clone()))
+                    .await;
+            report_dry_run(overlay.changes(), &overlay.removed(), &leaderboards);
+            return;
+        }
 
-    #[allow(dead_code)]
-    async fn process(self, chat_log_path: String) -> Vec<UserChatPerformance> {
-        let chat_log = self.parse_to_log_struct(chat_log_path);
-        self.process_from_log_object(chat_log).await
+        #[cfg(feature = "sqlite")]
+        let stream = run
+            .stream
+            .clone()
+            .expect("Leaderboards are exported after a stream");
+        #[cfg(feature = "sqlite")]
+        let recorded = performances.clone();
+        let _leaderboards =
+            Self::update_leaderboards(performances, run, settings, store.clone()).await;
+        #[cfg(feature = "sqlite")]
+        record_in_database(&stream, &recorded, store.as_ref(), &_leaderboards);
     }
 
-    /// A function to export the user performances to the leaderboards and save them
-    pub async fn export_to_leaderboards(performances: Vec<UserChatPerformance>) {
-        let mut leaderboard_processor = LeaderboardProcessor::new();
+    /// Updates the leaderboards kept in a store with the performances of a
+    /// run, and returns their names
+    pub async fn update_leaderboards(
+        performances: Vec<UserChatPerformance>,
+        run: RunInfo,
+        settings: &Settings,
+        store: Arc<dyn LeaderboardStore>,
+    ) -> Vec<String> {
+        let config = match &settings.config {
+            Some(path) => LeaderboardsConfig::from_file(&path.to_string_lossy())
+                .expect("Could not load leaderboard definitions"),
+            None => LeaderboardsConfig::default(),
+        };
+        let config = if env::var("EXPLAIN_LEADERBOARDS").as_deref() == Ok("1") {
+            config.with_explanations()
+        } else {
+            config
+        };
+        let config = match env::var("EXPORT_FORMATS") {
+            Ok(formats) => {
+                let formats: Vec<ExportFormat> = formats
+                    .split(',')
+                    .filter(|format| !format.trim().is_empty())
+                    .map(|format| format.parse().expect("Invalid export format"))
+                    .collect();
+                config.with_formats(&formats)
+            }
+            Err(_) => config,
+        };
+        let mut leaderboard_processor = LeaderboardProcessor::from_config_with_store(
+            config.with_default_k(scoring_config(settings).leaderboards.k),
+            store,
+        )
+        .with_run(RunInfo {
+            season: season(settings).map(|season| season.id().to_string()),
+            config_hash: Some(config_hash(settings)),
+            ..run
+        });
+
         leaderboard_processor.run(performances).await;
+        leaderboard_processor.names()
     }
 }
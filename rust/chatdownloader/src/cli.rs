@@ -0,0 +1,111 @@
+/*
+Command line interface of the chatdownloader
+*/
+
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
+
+/// Downloads chat logs and updates the leaderboards with them
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    #[command(flatten)]
+    pub settings: Settings,
+
+    /// Processes the latest VOD if no command is given
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Options shared by every command. They can also be set with the
+/// environment variables named in `--help`.
+#[derive(Debug, Clone, Default, Args)]
+pub struct Settings {
+    /// Where the leaderboards are saved [default: the working directory]
+    #[arg(long, global = true, env = "LEADERBOARD_DIRECTORY")]
+    pub output_dir: Option<PathBuf>,
+
+    /// Leaderboard definitions to use instead of the default leaderboards
+    #[arg(long, global = true, env = "LEADERBOARD_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Scoring config to use instead of the default scoring
+    #[arg(long, global = true, env = "SCORING_CONFIG")]
+    pub scoring_config: Option<PathBuf>,
+
+    /// Data snapshot to read the VODs, badges and 7TV emotes from instead
+    /// of the Twitch and 7TV APIs, as saved with SAVE_DATA_SNAPSHOT
+    #[arg(long, global = true, env = "CHAT_DATA_SNAPSHOT")]
+    pub snapshot: Option<PathBuf>,
+
+    /// Where downloaded chat logs are cached [default: chat-cache]
+    #[arg(long, global = true, env = "CHAT_CACHE_DIRECTORY")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Download the chat logs again even if they are cached
+    #[arg(long, global = true)]
+    pub refresh: bool,
+
+    /// Update the leaderboards in memory and print them, without saving
+    /// them or starting a new season
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Updates the leaderboards with streams, by default the latest VOD
+    Process(ProcessArgs),
+    /// Updates the leaderboards with several past VODs, oldest first
+    Backfill(BackfillArgs),
+    /// Prints a leaderboard file
+    Inspect {
+        /// The leaderboard's .bin file
+        path: PathBuf,
+    },
+    /// Rebuilds the leaderboards from the performances in the database,
+    /// e.g. after changing the leaderboard definitions. Needs the sqlite
+    /// feature.
+    Recompute(RecomputeArgs),
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ProcessArgs {
+    /// VODs to download and process, in order
+    #[arg(long, num_args = 1.., conflicts_with = "file")]
+    pub vod: Vec<String>,
+
+    /// Chat logs downloaded with TwitchDownloader, instead of downloading
+    /// them. Logs of the same VOD are merged, and each VOD is processed in
+    /// order.
+    #[arg(long, num_args = 1..)]
+    pub file: Vec<PathBuf>,
+
+    /// Discord chat exported with DiscordChatExporter, to process with
+    /// --file. Each message goes to the VOD that was live when it was sent.
+    #[arg(long, num_args = 1.., requires = "file")]
+    pub discord: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+#[group(multiple = false)]
+pub struct BackfillArgs {
+    /// Backfills the VODs that started on or after this date (YYYY-MM-DD)
+    #[arg(long)]
+    pub since: Option<NaiveDate>,
+
+    /// Backfills the latest N VODs [default: 5]
+    #[arg(long)]
+    pub count: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+#[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+pub struct RecomputeArgs {
+    /// Only replays the streams that started on or after this date
+    /// (YYYY-MM-DD), e.g. the start of the season
+    #[arg(long)]
+    pub since: Option<NaiveDate>,
+}
@@ -1,10 +1,11 @@
+use super::chatcache::{has_ended, ChatCache};
 use super::github::{extract_zip_blob, get_blob_url};
 use chrono::{DateTime, Utc};
 use discord_utils::DiscordChatLogs;
-use log::info;
+use log::{info, warn};
 use tempfile::{Builder, TempDir};
 
-use std::fs::{self, File};
+use std::fs;
 use std::io;
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
@@ -17,11 +18,12 @@ pub struct DiscordChatDownloader {
     executable_directory: TempDir,
     executable_path: String,
     downloaded: bool,
+    cache: ChatCache,
 }
 
 /// Downloads Discord Chat Logs
 impl DiscordChatDownloader {
-    pub fn new() -> Self {
+    pub fn new(cache: ChatCache) -> Self {
         let temporary_directory = TempDir::new().expect("Cannot create temporary directory");
         let mut executable_path_buf = temporary_directory.path().to_path_buf();
         executable_path_buf.push("DiscordChatExporter.Cli");
@@ -33,6 +35,7 @@ impl DiscordChatDownloader {
                 .expect("can construct executable")
                 .to_string(),
             downloaded: false,
+            cache,
         }
     }
 
@@ -59,6 +62,7 @@ impl DiscordChatDownloader {
         Ok(())
     }
 
+    /// Downloads the chat of a channel between two times, unless it is cached
     pub async fn download_chat(
         &mut self,
         start_datetime: DateTime<Utc>,
@@ -66,6 +70,12 @@ impl DiscordChatDownloader {
         channel_id: &str,
         discord_token: &str,
     ) -> Result<DiscordChatLogs, Box<dyn std::error::Error>> {
+        let key = ChatCache::discord_key(channel_id, start_datetime, end_datetime);
+        if let Some(contents) = self.cache.get(&key) {
+            info!("Using the cached Discord chat {}", key);
+            return Ok(serde_json::from_slice(&contents)?);
+        }
+
         if !self.downloaded {
             info!("No executable downloaded, downloading...");
             self.download_executable().await?;
@@ -107,15 +117,18 @@ impl DiscordChatDownloader {
             .status()?
             .success()
         {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::Other,
-                "Failed to download Discord chat",
-            )));
+            return Err(Box::new(io::Error::other("Failed to download Discord chat")));
         }
 
+        let contents = fs::read(&output_path)?;
+        fs::remove_file(&output_path)?;
+        /* Messages can still be sent until the time range is over */
+        if !has_ended(end_datetime) {
+            info!("Not caching the Discord chat {}, it isn't over yet", key);
+        } else if let Err(e) = self.cache.put(&key, &contents) {
+            warn!("Could not cache the Discord chat {}: {}", key, e);
+        }
         info!("Parsing JSON");
-        Ok(serde_json::from_reader::<File, DiscordChatLogs>(
-            File::open(&output_path)?,
-        )?)
+        Ok(serde_json::from_slice(&contents)?)
     }
 }
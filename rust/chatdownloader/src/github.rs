@@ -57,10 +57,7 @@ pub async fn extract_zip_blob(
 ) -> Result<ZipArchive<File>, Box<dyn std::error::Error>> {
     info!("Downloading blob from {url} into {:#?}", path.to_str());
     if !path.is_dir() {
-        return Err(Box::new(io::Error::new(
-            io::ErrorKind::Other,
-            "Failed to fetch release",
-        )));
+        return Err(Box::new(io::Error::other("Failed to fetch release")));
     }
     let mut temp_file = NamedTempFile::new_in(path)?;
     io::copy(
@@ -0,0 +1,98 @@
+/*
+Prints leaderboards, for the inspect command and dry runs
+*/
+
+use std::fs;
+use std::path::Path;
+
+use elo::_types::leaderboardtypes::LeaderboardExport;
+use elo::leaderboards::store::checked::matches_checksum;
+use elo::leaderboards::store::memory::MemoryStore;
+use elo::leaderboards::store::storetrait::LeaderboardStore;
+use prost::Message;
+
+/// Users shown when inspecting a leaderboard
+const INSPECT_TOP: usize = 20;
+
+/// Users shown for every leaderboard after a dry run
+const DRY_RUN_TOP: usize = 10;
+
+/// Prints the header and top users of a leaderboard
+pub fn print_leaderboard(name: &str, export: &LeaderboardExport, top: usize) {
+    println!("{}: {} users", name, export.items.len());
+    match &export.header {
+        Some(header) => {
+            let timestamp = |timestamp: &Option<prost_types::Timestamp>| {
+                timestamp
+                    .as_ref()
+                    .map(|timestamp| timestamp.to_string())
+                    .unwrap_or("-".to_string())
+            };
+            println!("  Stream end:   {}", timestamp(&header.stream_end));
+            println!("  VODs:         {}", header.vod_ids.join(", "));
+            println!(
+                "  Discord:      {} to {}",
+                timestamp(&header.discord_start),
+                timestamp(&header.discord_end)
+            );
+            println!("  Season:       {}", header.season);
+            println!("  Version:      {}", header.tool_version);
+            println!("  Config hash:  {}", header.config_hash);
+            println!("  Participants: {}", header.participants);
+            println!("  Messages:     {}", header.messages);
+        }
+        None => println!("  No header"),
+    }
+    println!(
+        "  {:>6}  {:<25} {:>9} {:>6}",
+        "Rank", "User", "Elo", "Delta"
+    );
+    for item in export.items.iter().take(top) {
+        println!(
+            "  {:>6}  {:<25} {:>9.2} {:>+6}",
+            item.rank, item.username, item.elo, item.delta
+        );
+    }
+}
+
+/// Prints a leaderboard file, and whether it matches its checksum
+pub fn inspect(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let data = fs::read(path)?;
+    let export = LeaderboardExport::decode(&*data)?;
+
+    let checksum_path = format!("{}.sha256", path.to_string_lossy());
+    match fs::read_to_string(&checksum_path) {
+        Ok(expected) if matches_checksum(&expected, &data) => println!("Checksum matches"),
+        Ok(_) => println!("Checksum DOES NOT match {}", checksum_path),
+        Err(_) => println!("No checksum"),
+    }
+
+    let name = path
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    print_leaderboard(&name, &export, INSPECT_TOP);
+    Ok(())
+}
+
+/// Shows what a dry run would have saved
+pub fn report_dry_run(changes: &MemoryStore, removed: &[String], leaderboards: &[String]) {
+    println!("Dry run, nothing was saved. These files would have been written:");
+    for key in changes.keys() {
+        println!("  {}", key);
+    }
+    if !removed.is_empty() {
+        println!("These files would have been deleted:");
+        for key in removed {
+            println!("  {}", key);
+        }
+    }
+    for leaderboard in leaderboards {
+        let Ok(Some(data)) = changes.read(&format!("{}.bin", leaderboard)) else {
+            continue;
+        };
+        let export = LeaderboardExport::decode(&*data).expect("Leaderboards are saved as exports");
+        println!();
+        print_leaderboard(leaderboard, &export, DRY_RUN_TOP);
+    }
+}
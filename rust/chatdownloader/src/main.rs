@@ -1,20 +1,74 @@
 mod backfill;
+mod chatcache;
 mod chatlogprocessor;
+mod cli;
 mod discorddownloaderproxy;
 mod github;
+mod inspect;
+mod process;
+#[cfg(feature = "sqlite")]
+mod recompute;
 mod twitchdownloaderproxy;
 
-use elo::_types::clptypes::Message;
+use clap::Parser;
+use cli::{Cli, Command, ProcessArgs, Settings};
 use env_logger::Env;
-use log::info;
+use log::{error, info};
 use std::{env, process::exit, sync::Arc};
+use twitch_utils::datasource::{ChatDataSource, DataSnapshot, LiveDataSource, SnapshotDataSource};
 use twitch_utils::{seventvclient::SevenTVClient, TwitchAPIWrapper};
 
 const CHANNEL_ID: &str = "1067638175478071307";
 
+/// Gets the Twitch/7TV data source to use.
+///
+/// If `--snapshot` is set, the data is read from that JSON snapshot and no
+/// credentials are needed. Otherwise the live APIs are used, and if
+/// `SAVE_DATA_SNAPSHOT` is set a snapshot of their data is saved there.
+async fn data_source(settings: &Settings) -> Box<dyn ChatDataSource> {
+    if let Some(path) = &settings.snapshot {
+        return Box::new(
+            SnapshotDataSource::from_file(&path.to_string_lossy())
+                .expect("Could not load the data snapshot"),
+        );
+    }
+
+    info!("Authenticating with Twitch...");
+    let source = LiveDataSource::new(TwitchAPIWrapper::new().await.unwrap());
+
+    if let Ok(path) = env::var("SAVE_DATA_SNAPSHOT") {
+        DataSnapshot::record(&source, elo::_constants::VED_CH_ID.to_string(), 5)
+            .await
+            .and_then(|snapshot| snapshot.save(&path))
+            .expect("Could not save the data snapshot");
+    }
+
+    Box::new(source)
+}
+
+/// Starts the season if it is new, archiving the previous one. Setting
+/// `SEASON_SOFT_RESET` (0 to 1) seeds the new season's ratings from the
+/// previous season's, moved that much of the way back to the initial elo.
+fn start_season(settings: &Settings) {
+    let Some(season) = chatlogprocessor::season(settings) else {
+        return;
+    };
+    let soft_reset = env::var("SEASON_SOFT_RESET").ok().map(|soft_reset| {
+        soft_reset
+            .parse::<f32>()
+            .expect("SEASON_SOFT_RESET must be a number")
+    });
+    season
+        .start(soft_reset)
+        .expect("Could not start the season");
+    info!("Season {}", season.id());
+}
+
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
+    let cli = Cli::parse();
+    let settings = cli.settings;
 
     let env = Env::default()
         .filter_or("MY_LOG_LEVEL", "info")
@@ -22,57 +76,71 @@ async fn main() {
 
     env_logger::init_from_env(env);
 
-    if env::var("BACKFILL").as_deref() == Ok("1") {
-        backfill::backfill().await;
-        exit(0);
+    let command = cli
+        .command
+        .unwrap_or(Command::Process(ProcessArgs::default()));
+
+    if let Command::Inspect { path } = &command {
+        if let Err(e) = inspect::inspect(path) {
+            error!("Could not inspect {:?}: {}", path, e);
+            exit(1);
+        }
+        return;
     }
 
-    info!("Authenticating with Twitch...");
+    #[cfg(not(feature = "sqlite"))]
+    if let Command::Recompute(_) = &command {
+        error!("recompute replays the performances in the database, which needs chatdownloader to be built with --features sqlite");
+        exit(1);
+    }
 
-    let twitch = TwitchAPIWrapper::new().await.unwrap();
-    let vod_id = twitch
-        .get_latest_vod_ids(elo::_constants::VED_CH_ID.to_string(), 1)
-        .await[0]
-        .clone();
-
-    info!("Script triggered, pulling logs for VOD ID: {}...", vod_id);
-
-    let mut downloader = twitchdownloaderproxy::TwitchChatDownloader::new();
-    let chat_log = downloader
-        .download_chat(&vod_id)
-        .await
-        .expect("Failed to download chat")
-        .comments
-        .into_iter()
-        .map(Message::Twitch);
-
-    let discord_messages = match env::var("CHAT_DISCORD_TOKEN") {
-        Ok(token) => {
-            let (start_time, end_time) = twitch.get_vod_times(vod_id).await;
-            discorddownloaderproxy::DiscordChatDownloader::new()
-                .download_chat(
-                    start_time.into(),
-                    end_time.into(),
-                    CHANNEL_ID,
-                    token.as_str(),
+    if !settings.dry_run {
+        start_season(&settings);
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Command::Recompute(args) = &command {
+        recompute::recompute(args, &settings).await;
+        return;
+    }
+
+    let source = data_source(&settings).await;
+    let seventv_client = Arc::new(SevenTVClient::from_source(source.as_ref()).await);
+
+    match command {
+        Command::Process(ProcessArgs { file, discord, .. }) if !file.is_empty() => {
+            if let Err(e) =
+                process::process_files(source.as_ref(), seventv_client, &file, &discord, &settings)
+                    .await
+            {
+                error!("{}", e);
+                exit(1);
+            }
+        }
+        Command::Process(ProcessArgs { vod, .. }) => {
+            let vod_ids = if vod.is_empty() {
+                source
+                    .get_latest_vod_ids(elo::_constants::VED_CH_ID.to_string(), 1)
+                    .await
+            } else {
+                vod
+            };
+            let mut downloader =
+                twitchdownloaderproxy::TwitchChatDownloader::new(chatcache::chat_cache(&settings));
+            for vod_id in vod_ids.iter() {
+                process::process_vod(
+                    source.as_ref(),
+                    seventv_client.clone(),
+                    &mut downloader,
+                    vod_id,
+                    &settings,
                 )
-                .await
-                .expect("Failed to download Discord chat")
-                .messages
+                .await;
+            }
         }
-        _ => {
-            vec![]
+        Command::Backfill(args) => {
+            backfill::backfill(source.as_ref(), seventv_client, &args, &settings).await;
         }
+        Command::Inspect { .. } | Command::Recompute(_) => unreachable!("Handled above"),
     }
-    .into_iter()
-    .map(Message::Discord);
-
-    let seventv_client = Arc::new(SevenTVClient::new().await);
-
-    let processor = chatlogprocessor::ChatLogProcessor::new(&twitch, seventv_client).await;
-    // let chat_log = processor.__parse_to_log_struct("chat.json".to_string());
-    let user_performances = processor
-        .process_from_messages(chat_log.chain(discord_messages))
-        .await;
-    chatlogprocessor::ChatLogProcessor::export_to_leaderboards(user_performances).await;
 }
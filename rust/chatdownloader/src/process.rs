@@ -0,0 +1,364 @@
+/*
+Functions to update the leaderboards with a stream's chat
+*/
+
+use std::collections::HashSet;
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use discord_utils::{DiscordChatLogs, DiscordMessage};
+use elo::_types::clptypes::Message;
+use elo::leaderboards::header::RunInfo;
+use log::{error, info, warn};
+use twitch_utils::datasource::{ChatDataSource, VodInfo};
+use twitch_utils::seventvclient::SevenTVClient;
+use twitch_utils::twitchtypes::Comment;
+
+use crate::chatcache::chat_cache;
+use crate::chatlogprocessor::ChatLogProcessor;
+use crate::cli::Settings;
+use crate::twitchdownloaderproxy::TwitchChatDownloader;
+use crate::{discorddownloaderproxy, CHANNEL_ID};
+
+/// Downloads the chat of a VOD, along with the Discord chat while it was
+/// live if `CHAT_DISCORD_TOKEN` is set, and updates the leaderboards
+pub async fn process_vod(
+    source: &dyn ChatDataSource,
+    seventv_client: Arc<SevenTVClient>,
+    downloader: &mut TwitchChatDownloader,
+    vod_id: &str,
+    settings: &Settings,
+) {
+    info!("Pulling logs for VOD ID: {}...", vod_id);
+    let chat_log = downloader
+        .download_chat(vod_id)
+        .await
+        .expect("Failed to download chat");
+
+    let stream = match source.get_vod_info(vod_id.to_string()).await {
+        Ok(stream) => stream,
+        Err(e) => match chat_log.video.as_ref().and_then(|video| video.times()) {
+            Some((start, end)) => {
+                warn!("Using the VOD times in the chat log of {}: {}", vod_id, e);
+                VodInfo {
+                    id: vod_id.to_string(),
+                    start,
+                    end,
+                }
+            }
+            None => {
+                error!("Skipping VOD {}, its times aren't known: {}", vod_id, e);
+                return;
+            }
+        },
+    };
+    let comments = chat_log.comments;
+
+    let (discord_messages, discord_range) = match env::var("CHAT_DISCORD_TOKEN") {
+        Ok(token) => (
+            discorddownloaderproxy::DiscordChatDownloader::new(chat_cache(settings))
+                .download_chat(
+                    stream.start.into(),
+                    stream.end.into(),
+                    CHANNEL_ID,
+                    token.as_str(),
+                )
+                .await
+                .expect("Failed to download Discord chat")
+                .messages,
+            Some((stream.start, stream.end)),
+        ),
+        _ => (vec![], None),
+    };
+    let run = RunInfo {
+        discord_range,
+        ..RunInfo::new(stream)
+    };
+
+    process_messages(
+        source,
+        seventv_client,
+        comments,
+        discord_messages,
+        run,
+        settings,
+    )
+    .await;
+}
+
+/// Updates the leaderboards with chat logs downloaded with TwitchDownloader,
+/// and Discord chat exported with DiscordChatExporter, without downloading
+/// anything
+///
+/// Chat logs of the same VOD are merged, and each VOD is processed as its
+/// own stream, oldest first. Like when downloading it, each Discord message
+/// goes to the VOD that was live when it was sent. The VOD times come from
+/// the chat logs if TwitchDownloader saved them, otherwise from the data
+/// source. Nothing is processed if any of the files can't be read.
+pub async fn process_files(
+    source: &dyn ChatDataSource,
+    seventv_client: Arc<SevenTVClient>,
+    chat_logs: &[PathBuf],
+    discord_logs: &[PathBuf],
+    settings: &Settings,
+) -> Result<(), Box<dyn Error>> {
+    let streams = read_chat_logs(source, chat_logs).await?;
+    let mut discord_messages = read_discord_logs(discord_logs)?;
+
+    let total_discord_messages = discord_messages.len();
+    let mut matched_discord_messages = 0;
+    for (stream, comments) in streams {
+        let (stream_messages, rest): (Vec<_>, Vec<_>) =
+            discord_messages.into_iter().partition(|message| {
+                let timestamp = message.timestamp.fixed_offset();
+                stream.start <= timestamp && timestamp <= stream.end
+            });
+        discord_messages = rest;
+        matched_discord_messages += stream_messages.len();
+
+        let run = RunInfo {
+            discord_range: (!discord_logs.is_empty()).then_some((stream.start, stream.end)),
+            ..RunInfo::new(stream)
+        };
+        process_messages(
+            source,
+            seventv_client.clone(),
+            comments,
+            stream_messages,
+            run,
+            settings,
+        )
+        .await;
+    }
+
+    if matched_discord_messages < total_discord_messages {
+        warn!(
+            "{} of {} Discord messages weren't sent during any of the VODs and were ignored",
+            total_discord_messages - matched_discord_messages,
+            total_discord_messages
+        );
+    }
+    Ok(())
+}
+
+/// Reads chat logs into the streams they are from, in the order the
+/// streams happened. The comments of each stream are deduplicated and in
+/// the order they were sent.
+async fn read_chat_logs(
+    source: &dyn ChatDataSource,
+    chat_logs: &[PathBuf],
+) -> Result<Vec<(VodInfo, Vec<Comment>)>, Box<dyn Error>> {
+    let mut streams: Vec<(VodInfo, Vec<Comment>)> = Vec::new();
+    for path in chat_logs {
+        info!("Reading chat log {:?}...", path);
+        let chat_log = ChatLogProcessor::parse_to_log_struct(path)
+            .map_err(|e| format!("Could not read the chat log {:?}: {}", path, e))?;
+        let video = chat_log
+            .video
+            .ok_or_else(|| format!("{:?} doesn't say which VOD it is from", path))?;
+
+        match streams.iter_mut().find(|(stream, _)| stream.id == video.id) {
+            Some((_, comments)) => comments.extend(chat_log.comments),
+            None => {
+                let stream = match video.times() {
+                    Some((start, end)) => VodInfo {
+                        id: video.id,
+                        start,
+                        end,
+                    },
+                    None => match source.get_vod_info(video.id).await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            error!("Skipping {:?}, its VOD times aren't known: {}", path, e);
+                            continue;
+                        }
+                    },
+                };
+                streams.push((stream, chat_log.comments));
+            }
+        }
+    }
+    /* The leaderboards expect the streams in the order they happened */
+    streams.sort_by_key(|(stream, _)| stream.start);
+
+    for (_, comments) in streams.iter_mut() {
+        let mut seen = HashSet::new();
+        comments.retain(|comment| seen.insert(comment._id.clone()));
+        /* Merged logs are in the order they were given, not sent */
+        comments.sort_by(|a, b| {
+            a.content_offset_seconds
+                .unwrap_or_default()
+                .total_cmp(&b.content_offset_seconds.unwrap_or_default())
+        });
+    }
+    Ok(streams)
+}
+
+/// Reads the messages of Discord chat logs, without the ones that are in
+/// more than one of them
+fn read_discord_logs(discord_logs: &[PathBuf]) -> Result<Vec<DiscordMessage>, Box<dyn Error>> {
+    let mut discord_messages = Vec::new();
+    for path in discord_logs {
+        info!("Reading Discord chat {:?}...", path);
+        discord_messages.extend(
+            parse_discord_log(path)
+                .map_err(|e| format!("Could not read the Discord chat {:?}: {}", path, e))?
+                .messages,
+        );
+    }
+    /* Overlapping logs would count the same messages twice */
+    let mut seen = HashSet::new();
+    discord_messages.retain(|message| seen.insert(message.id.clone()));
+    Ok(discord_messages)
+}
+
+/// Reads Discord chat exported with DiscordChatExporter
+fn parse_discord_log(path: &Path) -> Result<DiscordChatLogs, Box<dyn Error>> {
+    Ok(serde_json::from_reader(File::open(path)?)?)
+}
+
+async fn process_messages(
+    source: &dyn ChatDataSource,
+    seventv_client: Arc<SevenTVClient>,
+    comments: Vec<Comment>,
+    discord_messages: Vec<DiscordMessage>,
+    run: RunInfo,
+    settings: &Settings,
+) {
+    let run = RunInfo {
+        messages: (comments.len() + discord_messages.len()) as u64,
+        ..run
+    };
+    let user_performances = ChatLogProcessor::new(source, seventv_client, settings)
+        .await
+        .process_from_messages(
+            comments
+                .into_iter()
+                .map(Message::Twitch)
+                .chain(discord_messages.into_iter().map(Message::Discord)),
+        )
+        .await;
+    ChatLogProcessor::export_to_leaderboards(user_performances, run, settings).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::TempDir;
+    use twitch_utils::datasource::{DataSnapshot, SnapshotDataSource};
+
+    use super::*;
+
+    fn comment(id: &str, offset: f64) -> serde_json::Value {
+        json!({
+            "_id": id,
+            "content_offset_seconds": offset,
+            "message": {
+                "body": "hi",
+                "bits_spent": 0,
+                "fragments": [{ "text": "hi", "emoticon": null }],
+                "user_badges": null,
+            },
+            "commenter": { "display_name": "user", "_id": "1", "logo": "" },
+        })
+    }
+
+    /// Writes a chat log of a VOD to the directory
+    fn chat_log(
+        directory: &TempDir,
+        name: &str,
+        vod: &str,
+        start: &str,
+        comments: Vec<serde_json::Value>,
+    ) -> PathBuf {
+        let path = directory.path().join(name);
+        let log = json!({
+            "comments": comments,
+            "video": { "id": vod, "created_at": start, "length": 3600.0 },
+        });
+        std::fs::write(&path, log.to_string()).unwrap();
+        path
+    }
+
+    async fn read(chat_logs: &[PathBuf]) -> Result<Vec<(VodInfo, Vec<Comment>)>, Box<dyn Error>> {
+        read_chat_logs(&SnapshotDataSource::new(DataSnapshot::default()), chat_logs).await
+    }
+
+    #[tokio::test]
+    async fn streams_are_read_in_the_order_they_happened() {
+        let directory = tempfile::tempdir().unwrap();
+        let logs = [
+            chat_log(
+                &directory,
+                "later.json",
+                "2",
+                "2024-05-02T18:00:00Z",
+                vec![comment("c", 30.0), comment("a", 10.0)],
+            ),
+            chat_log(
+                &directory,
+                "earlier.json",
+                "1",
+                "2024-05-01T18:00:00Z",
+                vec![comment("x", 5.0)],
+            ),
+            chat_log(
+                &directory,
+                "later-part.json",
+                "2",
+                "2024-05-02T18:00:00Z",
+                vec![comment("b", 20.0), comment("a", 10.0)],
+            ),
+        ];
+
+        let streams = read(&logs).await.unwrap();
+
+        let read: Vec<(&str, Vec<&str>)> = streams
+            .iter()
+            .map(|(stream, comments)| {
+                (
+                    stream.id.as_str(),
+                    comments
+                        .iter()
+                        .map(|comment| comment._id.as_str())
+                        .collect(),
+                )
+            })
+            .collect();
+        assert_eq!(read, [("1", vec!["x"]), ("2", vec!["a", "b", "c"])]);
+    }
+
+    #[tokio::test]
+    async fn a_bad_chat_log_is_an_error_naming_it() {
+        let directory = tempfile::tempdir().unwrap();
+        let good = chat_log(&directory, "good.json", "1", "2024-05-01T18:00:00Z", vec![]);
+        let malformed = directory.path().join("malformed.json");
+        std::fs::write(&malformed, "{\"comments\": [").unwrap();
+        let without_video = directory.path().join("without-video.json");
+        std::fs::write(&without_video, "{\"comments\": []}").unwrap();
+        let missing = directory.path().join("missing.json");
+
+        for bad in [malformed, without_video, missing] {
+            let error = read(&[good.clone(), bad.clone()]).await.unwrap_err();
+            assert!(
+                error.to_string().contains(&format!("{:?}", bad)),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn a_bad_discord_log_is_an_error_naming_it() {
+        let directory = tempfile::tempdir().unwrap();
+        let malformed = directory.path().join("discord.json");
+        std::fs::write(&malformed, "not json").unwrap();
+
+        let error = read_discord_logs(std::slice::from_ref(&malformed)).unwrap_err();
+
+        assert!(error.to_string().contains(&format!("{:?}", malformed)));
+    }
+}
@@ -0,0 +1,107 @@
+/*
+Rebuilds the leaderboards by replaying the performances recorded in the
+SQLite database
+*/
+
+use std::{env, sync::Arc};
+
+use elo::_types::leaderboardtypes::LeaderboardExport;
+use elo::database::Database;
+use elo::leaderboards::header::RunInfo;
+use elo::leaderboards::leaderboard_files;
+use elo::leaderboards::store::{
+    checked::{read_checked, write_checked},
+    memory::MemoryStore,
+    storetrait::LeaderboardStore,
+};
+use log::info;
+use prost::Message;
+
+use crate::chatlogprocessor::{leaderboard_store, season, ChatLogProcessor};
+use crate::cli::{RecomputeArgs, Settings};
+use crate::inspect::report_dry_run;
+
+/// Replays every stream in the database in `DATABASE` into fresh
+/// leaderboards, which then replace the saved ones along with their
+/// backups, history and pages. A season seeded from the previous one is
+/// replayed from its seed. The headers of the replayed leaderboards have
+/// no message counts, since the database doesn't keep the messages.
+pub async fn recompute(args: &RecomputeArgs, settings: &Settings) {
+    let path = env::var("DATABASE").expect("DATABASE must be set to recompute the leaderboards");
+    let database = Database::open(&path).expect("Could not open the database");
+    let streams = database.streams().expect("Could not read the streams");
+
+    let replay = MemoryStore::new();
+    if let Some(season) = season(settings) {
+        let seed = season.seed_store();
+        for key in seed.list("").expect("Could not list the season's seed") {
+            let Some(data) = read_checked(&seed, &key, 0, |data| {
+                LeaderboardExport::decode(data).is_ok()
+            })
+            .expect("Could not read the season's seed") else {
+                continue;
+            };
+            write_checked(&replay, &key, &data, 0).expect("Memory stores can always be written");
+            info!("Replaying {} from the seed of season {}", key, season.id());
+        }
+    }
+    let mut leaderboards = Vec::new();
+    for stream in streams.into_iter().filter(|stream| {
+        args.since
+            .is_none_or(|since| stream.start.date_naive() >= since)
+    }) {
+        let performances = database
+            .performances(&stream.id)
+            .expect("Could not read the performances");
+        info!(
+            "Replaying stream {} with {} performances",
+            stream.id,
+            performances.len()
+        );
+        leaderboards = ChatLogProcessor::update_leaderboards(
+            performances,
+            RunInfo::new(stream),
+            settings,
+            Arc::new(replay.clone()),
+        )
+        .await;
+    }
+
+    /* Files of the saved leaderboards the replay didn't write would be
+    left over from before, like the snapshots of older streams */
+    let store = leaderboard_store(settings);
+    let replayed = replay.keys();
+    let mut stale = Vec::new();
+    for leaderboard in leaderboards.iter() {
+        stale.extend(
+            leaderboard_files(store.as_ref(), leaderboard)
+                .expect("Could not list the saved leaderboards")
+                .into_iter()
+                .filter(|key| !replayed.contains(key)),
+        );
+    }
+
+    if settings.dry_run {
+        report_dry_run(&replay, &stale, &leaderboards);
+        return;
+    }
+    for key in replayed {
+        let data = replay
+            .read(&key)
+            .expect("Memory stores can always be read")
+            .expect("Listed keys exist");
+        store
+            .write(&key, &data)
+            .expect("Could not save the leaderboards");
+    }
+    for key in stale {
+        store
+            .remove(&key)
+            .expect("Could not delete the old leaderboard files");
+    }
+    info!(
+        "Recomputed {} leaderboards from {}",
+        leaderboards.len(),
+        path
+    );
+}
@@ -1,9 +1,10 @@
+use super::chatcache::{has_ended, ChatCache};
 use super::github::{extract_zip_blob, get_blob_url};
-use log::info;
+use log::{info, warn};
 use tempfile::{Builder, NamedTempFile, TempDir, TempPath};
 
-use std::fs::{self, File};
-use std::io::{self, Read};
+use std::fs;
+use std::io;
 use std::os::unix::fs::PermissionsExt;
 use std::process::Command;
 
@@ -14,13 +15,15 @@ const RELEASES_URL: &str = "https://api.github.com/repos/lay295/TwitchDownloader
 pub struct TwitchChatDownloader {
     executable_path: TempPath,
     downloaded: bool,
+    cache: ChatCache,
 }
 
 impl TwitchChatDownloader {
-    pub fn new() -> Self {
+    pub fn new(cache: ChatCache) -> Self {
         TwitchChatDownloader {
             executable_path: NamedTempFile::new().unwrap().into_temp_path(),
             downloaded: false,
+            cache,
         }
     }
 
@@ -43,10 +46,17 @@ impl TwitchChatDownloader {
         Ok(())
     }
 
+    /// Downloads the chat log of a VOD, unless it is cached
     pub async fn download_chat(
         &mut self,
         vod_id: &str,
     ) -> Result<ChatLog, Box<dyn std::error::Error>> {
+        let key = ChatCache::vod_key(vod_id);
+        if let Some(contents) = self.cache.get(&key) {
+            info!("Using the cached chat log of VOD {}", vod_id);
+            return Ok(serde_json::from_slice(&contents)?);
+        }
+
         if !self.downloaded {
             println!("No executable downloaded, downloading...");
             self.download_executable().await?;
@@ -62,17 +72,28 @@ impl TwitchChatDownloader {
             .status()?;
 
         if !status.success() {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::Other,
-                "Failed to download chat",
-            )));
+            return Err(Box::new(io::Error::other("Failed to download chat")));
         }
 
-        let mut file = File::open(&output_path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
+        let contents = fs::read(&output_path)?;
+        fs::remove_file(&output_path)?;
         info!("Parsing JSON");
-        let chat_log: ChatLog = serde_json::from_str(&contents)?;
+        let chat_log: ChatLog = serde_json::from_slice(&contents)?;
+
+        /* The chat of a VOD that is still live isn't complete yet */
+        let ended = chat_log
+            .video
+            .as_ref()
+            .and_then(|video| video.times())
+            .is_some_and(|(_, end)| has_ended(end.into()));
+        if !ended {
+            info!(
+                "Not caching the chat log of VOD {}, it may still be live",
+                vod_id
+            );
+        } else if let Err(e) = self.cache.put(&key, &contents) {
+            warn!("Could not cache the chat log of VOD {}: {}", vod_id, e);
+        }
         Ok(chat_log)
     }
 }
@@ -1,8 +1,40 @@
 use std::io::Result;
 fn main() -> Result<()> {
-    prost_build::compile_protos(
-        &["../../models/leaderboardExportTypes.proto"],
-        &["../../models/"],
-    )?;
+    prost_build::Config::new()
+        // Maps are encoded in order, so saving the same leaderboard gives the same bytes
+        .btree_map(["."])
+        // For the JSON export of leaderboards
+        .type_attribute(
+            ".leaderboardExportTypes.BadgeInformation",
+            "#[derive(serde::Serialize)]",
+        )
+        .type_attribute(
+            ".leaderboardExportTypes.LeaderboardExportItem",
+            "#[derive(serde::Serialize)]",
+        )
+        .type_attribute(
+            ".leaderboardExportTypes.LeaderboardExport",
+            "#[derive(serde::Serialize)]",
+        )
+        .type_attribute(
+            ".leaderboardExportTypes.LeaderboardExportHeader",
+            "#[derive(serde::Serialize)]",
+        )
+        .field_attribute(
+            ".leaderboardExportTypes.LeaderboardExportHeader.stream_end",
+            "#[serde(serialize_with = \"crate::_types::leaderboardtypes::serialize_timestamp\")]",
+        )
+        .field_attribute(
+            ".leaderboardExportTypes.LeaderboardExportHeader.discord_start",
+            "#[serde(serialize_with = \"crate::_types::leaderboardtypes::serialize_timestamp\")]",
+        )
+        .field_attribute(
+            ".leaderboardExportTypes.LeaderboardExportHeader.discord_end",
+            "#[serde(serialize_with = \"crate::_types::leaderboardtypes::serialize_timestamp\")]",
+        )
+        .compile_protos(
+            &["../../models/leaderboardExportTypes.proto"],
+            &["../../models/"],
+        )?;
     Ok(())
 }
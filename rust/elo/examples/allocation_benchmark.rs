@@ -0,0 +1,115 @@
+//! Counts the allocations made processing a synthetic chat log with all of
+//! the built-in metrics, and the metadata that doesn't need Twitch.
+//!
+//! Run with `cargo run --release --example allocation_benchmark [messages]`,
+//! 500k messages from 5000 users by default. It only uses the processor's
+//! public API, so it can be copied onto older commits to compare them.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use elo::_types::clptypes::Message;
+use elo::MessageProcessorSetup;
+use serde_json::json;
+use twitch_utils::seventvclient::SevenTVClient;
+use twitch_utils::twitchtypes::Comment;
+
+const USERS: usize = 5000;
+
+/// The system allocator, counting every allocation it makes
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// A chat message, now and then a copypasta, gifted subs or bits
+fn message(i: usize) -> Message {
+    let user = i * 7919 % USERS;
+    let body = match i % 20 {
+        0 => "this is a copypasta that keeps getting pasted".to_string(),
+        1 => format!("user{} gifted a Tier 1 Sub to someone!", user),
+        _ => format!(
+            "message number {} from user {} with a few more words",
+            i, user
+        ),
+    };
+    let comment: Comment = serde_json::from_value(json!({
+        "_id": i.to_string(),
+        "message": {
+            "body": body,
+            "bits_spent": if i.is_multiple_of(50) { 100 } else { 0 },
+            "fragments": [{ "text": body, "emoticon": null }],
+            "user_badges": [{ "_id": "subscriber", "version": "12" }],
+        },
+        "commenter": {
+            "display_name": format!("User{}", user),
+            "_id": format!("id{}", user),
+            "logo": "https://example.com/logo.png",
+        },
+    }))
+    .unwrap();
+    Message::from(comment)
+}
+
+#[tokio::main]
+async fn main() {
+    let messages: usize = env::args()
+        .nth(1)
+        .map(|messages| {
+            messages
+                .parse()
+                .expect("The number of messages must be a number")
+        })
+        .unwrap_or(500_000);
+    let log: Vec<Message> = (0..messages).map(message).collect();
+
+    let processor = MessageProcessorSetup::builder(Arc::new(SevenTVClient::default()))
+        .disable_metadata("badges")
+        .build()
+        .await
+        .start()
+        .await;
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for message in log {
+        processor.process_message(message).await.unwrap();
+    }
+    let users = processor.finish().await.len();
+
+    println!(
+        "Processed {} messages from {} users in {:?}",
+        messages,
+        users,
+        start.elapsed()
+    );
+    println!(
+        "{} allocations, {} bytes allocated",
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        BYTES.load(Ordering::Relaxed) - bytes
+    );
+}
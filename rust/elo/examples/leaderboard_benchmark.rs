@@ -0,0 +1,184 @@
+//! Times a leaderboard update with synthetic users, and checks it against
+//! comparing every user with every sampled opponent one by one.
+//!
+//! Run with `cargo run --release --example leaderboard_benchmark [users]`,
+//! 100k users by default. Exits with an error if the two disagree.
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use std::time::Instant;
+
+use elo::_types::clptypes::UserChatPerformance;
+use elo::_types::leaderboardtypes::LeaderboardInnerState;
+use elo::leaderboards::configured::ConfiguredLeaderboard;
+use elo::leaderboards::definition::LeaderboardsConfig;
+use elo::leaderboards::header::RunInfo;
+use elo::leaderboards::leaderboardtrait::AbstractLeaderboard;
+use elo::leaderboards::rating::elo::Elo;
+use elo::leaderboards::rating::ratingtrait::{Game, Rating, RatingSystem};
+use elo::leaderboards::store::memory::MemoryStore;
+
+/// A small deterministic random number generator, so runs are comparable
+struct Lcg(u64);
+
+impl Lcg {
+    /// A number from 0 to 1
+    fn next(&mut self) -> f32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Performances of a stream that `share` of the users chatted in. Scores
+/// are whole numbers, skewed towards 0, so many users tie.
+fn stream(users: usize, share: f32, random: &mut Lcg) -> Vec<UserChatPerformance> {
+    (0..users)
+        .filter_map(|user| {
+            if random.next() >= share {
+                return None;
+            }
+            let score = (random.next() * random.next() * 500.0).floor();
+            Some(UserChatPerformance {
+                id: format!("user{}", user),
+                username: format!("User {}", user),
+                avatar: String::new(),
+                metrics: HashMap::from([("text".to_string(), score)]),
+                metadata: HashMap::new(),
+            })
+        })
+        .collect()
+}
+
+/// Samples the opponents the straightforward way, searching all users for
+/// the closest one to every sample
+fn reference_opponents(
+    leaderboard: &ConfiguredLeaderboard,
+    state: &HashMap<String, LeaderboardInnerState>,
+) -> Vec<(f32, f32)> {
+    let participants: Vec<&LeaderboardInnerState> =
+        state.values().filter(|user| user.participated).collect();
+    let scores: Vec<f32> = participants.iter().map(|user| user.score).collect();
+    leaderboard
+        .percentiles(&scores, 0.0, 100.0, 0.1)
+        .into_iter()
+        .map(|score| {
+            let closest = participants
+                .iter()
+                .min_by(|a, b| {
+                    (a.score - score)
+                        .abs()
+                        .partial_cmp(&(b.score - score).abs())
+                        .unwrap()
+                        .then_with(|| a.id.cmp(&b.id))
+                })
+                .unwrap();
+            (score, closest.elo)
+        })
+        .collect()
+}
+
+/// Rates the users the straightforward way, playing every game
+fn reference_ratings(
+    state: &HashMap<String, LeaderboardInnerState>,
+    opponents: &[(f32, f32)],
+    elo: &Elo,
+) -> HashMap<String, f32> {
+    state
+        .values()
+        .map(|user| {
+            if !user.participated {
+                return (user.id.clone(), user.elo);
+            }
+            let games: Vec<Game> = opponents
+                .iter()
+                .map(|(score, opponent_elo)| Game {
+                    opponent: Rating {
+                        rating: *opponent_elo,
+                        deviation: None,
+                        volatility: None,
+                    },
+                    outcome: (user.score > *score) as u8 as f32,
+                })
+                .collect();
+            (user.id.clone(), elo.rate(user.rating(), &games).rating)
+        })
+        .collect()
+}
+
+fn main() {
+    let users: usize = env::args()
+        .nth(1)
+        .map(|users| users.parse().expect("The number of users must be a number"))
+        .unwrap_or(100_000);
+    let mut random = Lcg(2024);
+
+    let config: LeaderboardsConfig = "[[leaderboard]]\nname = \"overall\"\nscore = \"all\"\n"
+        .parse()
+        .unwrap();
+    let definition = config.leaderboards.into_iter().next().unwrap();
+    let mut leaderboard = ConfiguredLeaderboard::new(definition, Arc::new(MemoryStore::default()));
+
+    /* A first stream, so users don't all start the measured one at 1200 */
+    for performance in stream(users, 0.8, &mut random) {
+        leaderboard.update_leaderboard(performance);
+    }
+    let start = Instant::now();
+    leaderboard.save(&RunInfo::default());
+    println!(
+        "First stream of {} users saved in {:?}",
+        users,
+        start.elapsed()
+    );
+
+    for performance in stream(users, 0.5, &mut random) {
+        leaderboard.update_leaderboard(performance);
+    }
+    let state = leaderboard.__get_state().clone();
+    let elo = Elo {
+        k: leaderboard.get_k_factor(),
+    };
+
+    let start = Instant::now();
+    let reference = reference_opponents(&leaderboard, &state);
+    let expected = reference_ratings(&state, &reference, &elo);
+    let reference_time = start.elapsed();
+
+    let opponents: Vec<(f32, f32)> = leaderboard
+        .__sample_opponents()
+        .iter()
+        .map(|(score, rating)| (score, rating.rating))
+        .collect();
+
+    let start = Instant::now();
+    leaderboard.__calculate_new_elo();
+    let time = start.elapsed();
+
+    let largest_difference = leaderboard
+        .__get_state()
+        .values()
+        .map(|user| (user.elo - expected[&user.id]).abs())
+        .fold(0.0, f32::max);
+    println!("Rated {} users in {:?}", state.len(), time);
+    println!("Reference took {:?}", reference_time);
+    println!(
+        "Same opponents as the reference: {}",
+        opponents == reference
+    );
+    println!(
+        "Largest difference from the reference: {}",
+        largest_difference
+    );
+
+    /* Fails the CI run if the two ever disagree */
+    assert!(
+        opponents == reference,
+        "Sampled different opponents than the reference"
+    );
+    assert!(
+        largest_difference < 0.01,
+        "Ratings differ from the reference"
+    );
+}
@@ -2,12 +2,29 @@
 Publically accessible leaderboard types
 */
 
+use crate::leaderboards::explain::ScoreContribution;
+use crate::leaderboards::rating::ratingtrait::Rating;
+
 // Includes the protobuf types defined in models/leaderboardExportTypes.proto
 include!(concat!(env!("OUT_DIR"), "/leaderboard_export_types.rs"));
 
 impl From<Vec<LeaderboardExportItem>> for LeaderboardExport {
     fn from(items: Vec<LeaderboardExportItem>) -> Self {
-        LeaderboardExport { items }
+        LeaderboardExport {
+            items,
+            header: None,
+        }
+    }
+}
+
+/// Writes protobuf timestamps as RFC 3339 strings in the JSON export
+pub fn serialize_timestamp<S: serde::Serializer>(
+    timestamp: &Option<prost_types::Timestamp>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match timestamp {
+        Some(timestamp) => serializer.serialize_some(&timestamp.to_string()),
+        None => serializer.serialize_none(),
     }
 }
 
@@ -19,7 +36,33 @@ pub struct LeaderboardInnerState {
     pub badges: Option<Vec<BadgeInformation>>,
     pub previous_rank: Option<u32>,
     pub elo: f32,
+    pub deviation: Option<f32>,
+    pub volatility: Option<f32>,
+    /// Updates the user chatted in
+    pub rated_streams: u32,
+    /// Updates in a row the user didn't chat in
+    pub missed_streams: u32,
     pub score: f32,
+    /// Whether the user has a performance in this update
+    pub participated: bool,
+    /// Breakdown of the score, only kept when explaining updates
+    pub contributions: Option<Vec<ScoreContribution>>,
+}
+
+impl LeaderboardInnerState {
+    pub fn rating(&self) -> Rating {
+        Rating {
+            rating: self.elo,
+            deviation: self.deviation,
+            volatility: self.volatility,
+        }
+    }
+
+    pub fn set_rating(&mut self, rating: Rating) {
+        self.elo = rating.rating;
+        self.deviation = rating.deviation;
+        self.volatility = rating.volatility;
+    }
 }
 
 pub fn export_item_to_inner_state(item: LeaderboardExportItem) -> LeaderboardInnerState {
@@ -30,6 +73,12 @@ pub fn export_item_to_inner_state(item: LeaderboardExportItem) -> LeaderboardInn
         badges: Some(item.badges),
         previous_rank: Some(item.rank),
         elo: item.elo,
-        score: 1200.0,
+        deviation: item.deviation,
+        volatility: item.volatility,
+        rated_streams: item.rated_streams.unwrap_or_default(),
+        missed_streams: item.missed_streams,
+        score: 0.0,
+        participated: false,
+        contributions: None,
     }
 }
@@ -0,0 +1,631 @@
+//! Keeps users, stream performances and leaderboard ratings in an SQLite
+//! database, so they can be queried across streams. For example, the top
+//! copypasta leaders across all streams in July:
+//!
+//! ```sql
+//! SELECT users.username, SUM(performances.value) AS copypasta
+//! FROM performances
+//! JOIN streams USING (vod_id)
+//! JOIN users ON users.id = performances.user_id
+//! WHERE performances.metric = 'copypasta'
+//!   AND strftime('%m', streams.start, 'unixepoch') = '07'
+//! GROUP BY performances.user_id
+//! ORDER BY copypasta DESC
+//! LIMIT 10;
+//! ```
+//!
+//! The leaderboard `.bin` exports can be rebuilt from the database with
+//! `Database::export`, and the performances of every stream can be
+//! replayed with `Database::streams` and `Database::performances`.
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::DateTime;
+
+use prost::Message;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+use twitch_utils::datasource::VodInfo;
+
+use crate::_types::clptypes::{MessageTag, MetadataTypes, UserChatPerformance};
+use crate::_types::leaderboardtypes::{
+    BadgeInformation, LeaderboardExport, LeaderboardExportItem, LeaderboardSnapshot,
+};
+use crate::leaderboards::store::storetrait::LeaderboardStore;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY,
+    username TEXT NOT NULL,
+    avatar TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS streams (
+    vod_id TEXT PRIMARY KEY,
+    -- Unix timestamps
+    start INTEGER NOT NULL,
+    end INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS performances (
+    vod_id TEXT NOT NULL REFERENCES streams (vod_id),
+    user_id TEXT NOT NULL REFERENCES users (id),
+    metric TEXT NOT NULL,
+    value REAL NOT NULL,
+    PRIMARY KEY (vod_id, user_id, metric)
+);
+
+CREATE TABLE IF NOT EXISTS performance_metadata (
+    vod_id TEXT NOT NULL REFERENCES streams (vod_id),
+    user_id TEXT NOT NULL REFERENCES users (id),
+    name TEXT NOT NULL,
+    -- JSON
+    value TEXT NOT NULL,
+    PRIMARY KEY (vod_id, user_id, name)
+);
+
+-- The latest state of every leaderboard, as in its .bin export
+CREATE TABLE IF NOT EXISTS ratings (
+    leaderboard TEXT NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id),
+    rank INTEGER NOT NULL,
+    elo REAL NOT NULL,
+    delta INTEGER NOT NULL,
+    deviation REAL,
+    volatility REAL,
+    -- NULL for users saved before rated streams were counted
+    rated_streams INTEGER,
+    missed_streams INTEGER NOT NULL,
+    provisional INTEGER NOT NULL,
+    inactive INTEGER NOT NULL,
+    PRIMARY KEY (leaderboard, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS rating_badges (
+    leaderboard TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    description TEXT NOT NULL,
+    image_url TEXT NOT NULL,
+    PRIMARY KEY (leaderboard, user_id, position)
+);
+
+-- How every stream changed the leaderboards
+CREATE TABLE IF NOT EXISTS rating_changes (
+    leaderboard TEXT NOT NULL,
+    vod_id TEXT NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id),
+    rank INTEGER NOT NULL,
+    -- NULL if the user wasn't on the leaderboard before the stream
+    elo_before REAL,
+    elo REAL NOT NULL,
+    deviation REAL,
+    participated INTEGER NOT NULL,
+    PRIMARY KEY (leaderboard, vod_id, user_id)
+);
+";
+
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Opens the database at a path, creating it if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self, Box<dyn std::error::Error>> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// The connection, for queries of your own
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Records the performances of a stream, replacing any recorded before
+    /// for the same stream
+    pub fn record_stream(
+        &mut self,
+        stream: &VodInfo,
+        performances: &[UserChatPerformance],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO streams (vod_id, start, end) VALUES (?1, ?2, ?3)",
+            params![stream.id, stream.start.timestamp(), stream.end.timestamp()],
+        )?;
+        transaction.execute("DELETE FROM performances WHERE vod_id = ?1", [&stream.id])?;
+        transaction.execute(
+            "DELETE FROM performance_metadata WHERE vod_id = ?1",
+            [&stream.id],
+        )?;
+
+        for performance in performances {
+            transaction.execute(
+                "INSERT OR REPLACE INTO users (id, username, avatar) VALUES (?1, ?2, ?3)",
+                params![performance.id, performance.username, performance.avatar],
+            )?;
+            for (metric, value) in performance.metrics.iter() {
+                transaction.execute(
+                    "INSERT INTO performances (vod_id, user_id, metric, value)
+                    VALUES (?1, ?2, ?3, ?4)",
+                    params![stream.id, performance.id, metric, value],
+                )?;
+            }
+            for (name, value) in performance.metadata.iter() {
+                transaction.execute(
+                    "INSERT INTO performance_metadata (vod_id, user_id, name, value)
+                    VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        stream.id,
+                        performance.id,
+                        name,
+                        metadata_to_json(value).to_string()
+                    ],
+                )?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Records the state of a leaderboard, and how the stream in the
+    /// snapshot changed it
+    pub fn record_leaderboard(
+        &mut self,
+        leaderboard: &str,
+        export: &LeaderboardExport,
+        snapshot: Option<&LeaderboardSnapshot>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let transaction = self.connection.transaction()?;
+
+        if let Some(snapshot) = snapshot {
+            for item in snapshot.items.iter() {
+                let elo_before: Option<f32> = transaction
+                    .query_row(
+                        "SELECT elo FROM ratings WHERE leaderboard = ?1 AND user_id = ?2",
+                        params![leaderboard, item.id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                transaction.execute(
+                    "INSERT OR REPLACE INTO rating_changes
+                    (leaderboard, vod_id, user_id, rank, elo_before, elo, deviation, participated)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        leaderboard,
+                        snapshot.vod_id,
+                        item.id,
+                        item.rank,
+                        elo_before,
+                        item.elo,
+                        item.deviation,
+                        item.participated
+                    ],
+                )?;
+            }
+        }
+
+        transaction.execute("DELETE FROM ratings WHERE leaderboard = ?1", [leaderboard])?;
+        transaction.execute(
+            "DELETE FROM rating_badges WHERE leaderboard = ?1",
+            [leaderboard],
+        )?;
+        for item in export.items.iter() {
+            /* Keep a username someone chatted with over the one in the
+            leaderboard, which can be older */
+            transaction.execute(
+                "INSERT OR IGNORE INTO users (id, username, avatar) VALUES (?1, ?2, ?3)",
+                params![item.id, item.username, item.avatar],
+            )?;
+            transaction.execute(
+                "INSERT INTO ratings
+                (leaderboard, user_id, rank, elo, delta, deviation, volatility,
+                rated_streams, missed_streams, provisional, inactive)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    leaderboard,
+                    item.id,
+                    item.rank,
+                    item.elo,
+                    item.delta,
+                    item.deviation,
+                    item.volatility,
+                    item.rated_streams,
+                    item.missed_streams,
+                    item.provisional,
+                    item.inactive
+                ],
+            )?;
+            for (position, badge) in item.badges.iter().enumerate() {
+                transaction.execute(
+                    "INSERT INTO rating_badges
+                    (leaderboard, user_id, position, description, image_url)
+                    VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        leaderboard,
+                        item.id,
+                        position,
+                        badge.description,
+                        badge.image_url
+                    ],
+                )?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Records the leaderboards saved in a store after a stream
+    pub fn record_leaderboards(
+        &mut self,
+        store: &dyn LeaderboardStore,
+        leaderboards: &[String],
+        stream: &VodInfo,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for leaderboard in leaderboards {
+            let Some(data) = store.read(&format!("{}.bin", leaderboard))? else {
+                continue;
+            };
+            let export = LeaderboardExport::decode(&*data)?;
+            let snapshot = LeaderboardSnapshot::load(store, leaderboard, stream)?;
+            self.record_leaderboard(leaderboard, &export, snapshot.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// The recorded streams, oldest first
+    pub fn streams(&self) -> Result<Vec<VodInfo>, Box<dyn std::error::Error>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT vod_id, start, end FROM streams ORDER BY start, vod_id")?;
+        let streams = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        streams
+            .into_iter()
+            .map(|(id, start, end)| {
+                let timestamp = |seconds: i64| {
+                    DateTime::from_timestamp(seconds, 0)
+                        .map(|time| time.fixed_offset())
+                        .ok_or_else(|| format!("Stream {} has an invalid time", id))
+                };
+                Ok(VodInfo {
+                    start: timestamp(start)?,
+                    end: timestamp(end)?,
+                    id,
+                })
+            })
+            .collect()
+    }
+
+    /// The recorded performances of a stream, sorted by user id
+    pub fn performances(
+        &self,
+        vod_id: &str,
+    ) -> Result<Vec<UserChatPerformance>, Box<dyn std::error::Error>> {
+        let mut performances: HashMap<String, UserChatPerformance> = HashMap::new();
+
+        let mut statement = self.connection.prepare(
+            "SELECT users.id, username, avatar, metric, value
+            FROM performances JOIN users ON users.id = performances.user_id
+            WHERE vod_id = ?1",
+        )?;
+        let mut rows = statement.query([vod_id])?;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            let performance = performances
+                .entry(id.clone())
+                .or_insert(UserChatPerformance {
+                    id,
+                    username: row.get(1)?,
+                    avatar: row.get(2)?,
+                    metrics: HashMap::new(),
+                    metadata: HashMap::new(),
+                });
+            performance.metrics.insert(row.get(3)?, row.get(4)?);
+        }
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT user_id, name, value FROM performance_metadata WHERE vod_id = ?1")?;
+        let mut rows = statement.query([vod_id])?;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            let value: String = row.get(2)?;
+            if let Some(performance) = performances.get_mut(&id) {
+                performance.metadata.insert(
+                    row.get(1)?,
+                    json_to_metadata(&serde_json::from_str(&value)?)?,
+                );
+            }
+        }
+
+        let mut performances: Vec<UserChatPerformance> = performances.into_values().collect();
+        performances.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(performances)
+    }
+
+    /// Names of the leaderboards in the database
+    pub fn leaderboards(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT DISTINCT leaderboard FROM ratings ORDER BY leaderboard")?;
+        let names = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(names)
+    }
+
+    /// Rebuilds the `.bin` export of a leaderboard
+    pub fn export(
+        &self,
+        leaderboard: &str,
+    ) -> Result<LeaderboardExport, Box<dyn std::error::Error>> {
+        let mut statement = self.connection.prepare(
+            "SELECT ratings.user_id, rank, elo, username, delta, avatar, deviation, volatility,
+            rated_streams, missed_streams, provisional, inactive
+            FROM ratings JOIN users ON users.id = ratings.user_id
+            WHERE leaderboard = ?1
+            ORDER BY rank",
+        )?;
+        let mut items = statement
+            .query_map([leaderboard], |row| {
+                Ok(LeaderboardExportItem {
+                    id: row.get(0)?,
+                    rank: row.get(1)?,
+                    elo: row.get(2)?,
+                    username: row.get(3)?,
+                    delta: row.get(4)?,
+                    avatar: row.get(5)?,
+                    badges: Vec::new(),
+                    deviation: row.get(6)?,
+                    volatility: row.get(7)?,
+                    rated_streams: row.get(8)?,
+                    missed_streams: row.get(9)?,
+                    provisional: row.get(10)?,
+                    inactive: row.get(11)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut statement = self.connection.prepare(
+            "SELECT description, image_url FROM rating_badges
+            WHERE leaderboard = ?1 AND user_id = ?2
+            ORDER BY position",
+        )?;
+        for item in items.iter_mut() {
+            item.badges = statement
+                .query_map(params![leaderboard, item.id], |row| {
+                    Ok(BadgeInformation {
+                        description: row.get(0)?,
+                        image_url: row.get(1)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+        Ok(LeaderboardExport::from(items))
+    }
+}
+
+fn metadata_to_json(metadata: &MetadataTypes) -> Value {
+    match metadata {
+        MetadataTypes::Bool(value) => json!(value),
+        MetadataTypes::BadgeList(badges) => badges
+            .iter()
+            .map(|badge| {
+                json!({
+                    "description": badge.description,
+                    "image_url": badge.image_url,
+                })
+            })
+            .collect(),
+        MetadataTypes::BasicInfo(username, avatar) => json!({
+            "username": username,
+            "avatar": avatar,
+        }),
+        MetadataTypes::ChatOrigin(origin) => json!(format!("{:?}", origin)),
+    }
+}
+
+/// Reads back metadata saved by `metadata_to_json`, telling the types
+/// apart by the shape of the JSON
+fn json_to_metadata(value: &Value) -> Result<MetadataTypes, Box<dyn std::error::Error>> {
+    let invalid = || format!("Invalid metadata {}", value);
+    Ok(match value {
+        Value::Bool(value) => MetadataTypes::Bool(*value),
+        Value::Array(badges) => MetadataTypes::BadgeList(
+            badges
+                .iter()
+                .map(|badge| {
+                    Some(BadgeInformation {
+                        description: badge["description"].as_str()?.to_string(),
+                        image_url: badge["image_url"].as_str()?.to_string(),
+                    })
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(invalid)?,
+        ),
+        Value::Object(info) => MetadataTypes::BasicInfo(
+            info["username"].as_str().ok_or_else(invalid)?.to_string(),
+            info["avatar"].as_str().ok_or_else(invalid)?.to_string(),
+        ),
+        Value::String(origin) => MetadataTypes::ChatOrigin(match origin.as_str() {
+            "Twitch" => MessageTag::Twitch,
+            "Discord" => MessageTag::Discord,
+            "Emote" => MessageTag::Emote,
+            _ => MessageTag::None,
+        }),
+        _ => return Err(invalid().into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::_types::leaderboardtypes::LeaderboardSnapshotItem;
+
+    fn stream(id: &str, start: i64) -> VodInfo {
+        VodInfo {
+            id: id.to_string(),
+            start: DateTime::from_timestamp(start, 0).unwrap().fixed_offset(),
+            end: DateTime::from_timestamp(start + 3600, 0)
+                .unwrap()
+                .fixed_offset(),
+        }
+    }
+
+    fn badge() -> BadgeInformation {
+        BadgeInformation {
+            description: "Subscriber".to_string(),
+            image_url: "https://example.com/sub.png".to_string(),
+        }
+    }
+
+    fn performance(id: &str, text: f32) -> UserChatPerformance {
+        UserChatPerformance {
+            id: id.to_string(),
+            username: format!("user {}", id),
+            avatar: format!("https://example.com/{}.png", id),
+            metrics: HashMap::from([("text".to_string(), text), ("bits".to_string(), 0.0)]),
+            metadata: HashMap::from([
+                ("special_role".to_string(), MetadataTypes::Bool(true)),
+                (
+                    "badges".to_string(),
+                    MetadataTypes::BadgeList(vec![badge()]),
+                ),
+                (
+                    "basic_info".to_string(),
+                    MetadataTypes::BasicInfo("name".to_string(), "avatar".to_string()),
+                ),
+                (
+                    "chat_origin".to_string(),
+                    MetadataTypes::ChatOrigin(MessageTag::Discord),
+                ),
+            ]),
+        }
+    }
+
+    #[test]
+    fn performances_read_back_as_recorded() {
+        let mut database = Database::open_in_memory().unwrap();
+        database
+            .record_stream(&stream("2", 7200), &[performance("b", 2.0)])
+            .unwrap();
+        database
+            .record_stream(
+                &stream("1", 0),
+                &[performance("b", 1.0), performance("a", 3.0)],
+            )
+            .unwrap();
+        /* Recording a stream again replaces it */
+        database
+            .record_stream(&stream("2", 7200), &[performance("c", 4.0)])
+            .unwrap();
+
+        let streams: Vec<String> = database
+            .streams()
+            .unwrap()
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(streams, ["1", "2"]);
+        assert_eq!(database.streams().unwrap()[1].end, stream("2", 7200).end);
+
+        let performances = database.performances("1").unwrap();
+        assert_eq!(performances.len(), 2);
+        let expected = performance("a", 3.0);
+        assert_eq!(performances[0].id, "a");
+        assert_eq!(performances[0].username, expected.username);
+        assert_eq!(performances[0].metrics, expected.metrics);
+        for (name, value) in expected.metadata.iter() {
+            assert_eq!(
+                format!("{:?}", performances[0].metadata[name]),
+                format!("{:?}", value)
+            );
+        }
+
+        let replaced = database.performances("2").unwrap();
+        assert_eq!(replaced.len(), 1);
+        assert_eq!(replaced[0].id, "c");
+    }
+
+    #[test]
+    fn leaderboards_export_as_recorded() {
+        let mut database = Database::open_in_memory().unwrap();
+        let items = vec![
+            LeaderboardExportItem {
+                id: "a".to_string(),
+                rank: 1,
+                elo: 1300.0,
+                username: "user a".to_string(),
+                delta: 2,
+                avatar: "https://example.com/a.png".to_string(),
+                badges: vec![badge(), badge()],
+                deviation: Some(80.0),
+                volatility: Some(0.06),
+                rated_streams: Some(4),
+                missed_streams: 1,
+                provisional: false,
+                inactive: true,
+            },
+            LeaderboardExportItem {
+                id: "b".to_string(),
+                rank: 2,
+                elo: 1100.0,
+                username: "user b".to_string(),
+                rated_streams: None,
+                provisional: true,
+                ..Default::default()
+            },
+        ];
+        let export = LeaderboardExport::from(items);
+        database
+            .record_leaderboard("overall", &export, None)
+            .unwrap();
+
+        assert_eq!(database.leaderboards().unwrap(), ["overall"]);
+        assert_eq!(database.export("overall").unwrap(), export);
+
+        let snapshot = LeaderboardSnapshot {
+            vod_id: "2".to_string(),
+            items: vec![LeaderboardSnapshotItem {
+                id: "a".to_string(),
+                rank: 1,
+                elo: 1310.0,
+                deviation: None,
+                participated: true,
+            }],
+            ..Default::default()
+        };
+        let mut after = export.clone();
+        after.items[0].elo = 1310.0;
+        database
+            .record_leaderboard("overall", &after, Some(&snapshot))
+            .unwrap();
+
+        assert_eq!(database.export("overall").unwrap(), after);
+        let (elo_before, elo): (f32, f32) = database
+            .connection()
+            .query_row(
+                "SELECT elo_before, elo FROM rating_changes WHERE user_id = 'a'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((elo_before, elo), (1300.0, 1310.0));
+    }
+}
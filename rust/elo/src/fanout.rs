@@ -0,0 +1,199 @@
+//! Lossless fan-out of items to several consumers
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::{debug, warn};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// Number of items each consumer can fall behind by before the sender waits
+pub const DEFAULT_CAPACITY: usize = 10000;
+
+/// Error returned when an item cannot be delivered to every consumer
+#[derive(Debug)]
+pub enum FanOutError {
+    /// The consumer stopped receiving before the stream ended, so it
+    /// would have missed items
+    ConsumerClosed(String),
+}
+
+impl fmt::Display for FanOutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FanOutError::ConsumerClosed(name) => {
+                write!(f, "Consumer {} stopped before the end of the stream", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FanOutError {}
+
+struct Consumer<T> {
+    name: String,
+    sender: mpsc::Sender<T>,
+    stalls: AtomicU64,
+}
+
+/// Sends every item to every consumer, without ever dropping one.
+///
+/// Each consumer gets its own bounded channel. If a consumer falls behind
+/// and its channel fills up, `send` waits for it to catch up (backpressure)
+/// and counts the stall, so slow consumers show up in `report`. A consumer
+/// that stops receiving early is a hard error.
+pub struct FanOut<T> {
+    consumers: Vec<Consumer<T>>,
+    capacity: usize,
+}
+
+impl<T: Clone> FanOut<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            consumers: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Adds a consumer, returning the receiving end of its channel
+    pub fn subscribe(&mut self, name: impl Into<String>) -> mpsc::Receiver<T> {
+        let (sender, receiver) = mpsc::channel(self.capacity);
+        self.consumers.push(Consumer {
+            name: name.into(),
+            sender,
+            stalls: AtomicU64::new(0),
+        });
+        receiver
+    }
+
+    /// Sends an item to every consumer, waiting on any that are full
+    pub async fn send(&self, item: T) -> Result<(), FanOutError> {
+        for consumer in self.consumers.iter() {
+            let item = match consumer.sender.try_send(item.clone()) {
+                Ok(()) => continue,
+                Err(TrySendError::Closed(_)) => {
+                    return Err(FanOutError::ConsumerClosed(consumer.name.clone()))
+                }
+                Err(TrySendError::Full(item)) => item,
+            };
+
+            debug!(
+                "Consumer {} is full, waiting for it to catch up",
+                consumer.name
+            );
+            consumer.stalls.fetch_add(1, Ordering::Relaxed);
+            if consumer.sender.send(item).await.is_err() {
+                return Err(FanOutError::ConsumerClosed(consumer.name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of times each consumer was full when an item was sent
+    pub fn stalls(&self) -> Vec<(String, u64)> {
+        self.consumers
+            .iter()
+            .map(|consumer| {
+                (
+                    consumer.name.clone(),
+                    consumer.stalls.load(Ordering::Relaxed),
+                )
+            })
+            .collect()
+    }
+
+    /// Logs the consumers that held up the stream
+    pub fn report(&self) {
+        for (name, stalls) in self.stalls() {
+            if stalls > 0 {
+                warn!(
+                    "Consumer {} fell behind {} time(s); the stream waited for it to catch up",
+                    name, stalls
+                );
+            }
+        }
+    }
+
+    /// Closes every channel, signalling the end of the stream to the consumers
+    pub fn close(self) {
+        self.report();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn receive_all(mut receiver: mpsc::Receiver<u32>, slow: bool) -> Vec<u32> {
+        let mut items = Vec::new();
+        while let Some(item) = receiver.recv().await {
+            items.push(item);
+            if slow {
+                tokio::task::yield_now().await;
+            }
+        }
+        items
+    }
+
+    #[tokio::test]
+    async fn slow_consumers_hold_up_the_stream_without_losing_items() {
+        let mut fanout = FanOut::new(2);
+        let fast = tokio::spawn(receive_all(fanout.subscribe("fast"), false));
+        let slow = tokio::spawn(receive_all(fanout.subscribe("slow"), true));
+
+        for item in 0..100 {
+            fanout.send(item).await.unwrap();
+        }
+        fanout.close();
+
+        let expected: Vec<u32> = (0..100).collect();
+        assert_eq!(fast.await.unwrap(), expected);
+        assert_eq!(slow.await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn stalls_are_counted_for_the_consumer_that_was_full() {
+        let mut fanout = FanOut::new(1);
+        let mut drained = fanout.subscribe("drained");
+        let mut full = fanout.subscribe("full");
+        fanout.send(0).await.unwrap();
+        drained.recv().await.unwrap();
+
+        let send = fanout.send(1);
+        tokio::pin!(send);
+        assert!(futures::poll!(&mut send).is_pending());
+        assert_eq!(full.recv().await, Some(0));
+        send.await.unwrap();
+
+        assert_eq!(
+            fanout.stalls(),
+            vec![("drained".to_string(), 0), ("full".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn consumers_that_stop_early_are_an_error() {
+        let mut fanout = FanOut::new(1);
+        drop(fanout.subscribe("dropped"));
+
+        assert!(matches!(
+            fanout.send(0).await,
+            Err(FanOutError::ConsumerClosed(name)) if name == "dropped"
+        ));
+    }
+
+    #[tokio::test]
+    async fn consumers_that_stop_while_the_stream_waits_are_an_error() {
+        let mut fanout = FanOut::new(1);
+        let receiver = fanout.subscribe("dropped");
+        fanout.send(0).await.unwrap();
+
+        let send = fanout.send(1);
+        tokio::pin!(send);
+        assert!(futures::poll!(&mut send).is_pending());
+        drop(receiver);
+
+        assert!(matches!(
+            send.await,
+            Err(FanOutError::ConsumerClosed(name)) if name == "dropped"
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+//! A leaderboard built from a `LeaderboardDefinition`
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::_types::clptypes::UserChatPerformance;
+use crate::_types::leaderboardtypes::LeaderboardInnerState;
+use crate::leaderboards::definition::{AbsencePolicy, LeaderboardDefinition};
+use crate::leaderboards::explain::ScoreContribution;
+use crate::leaderboards::export::{ExportFormat, DEFAULT_PAGE_SIZE};
+use crate::leaderboards::leaderboardtrait::AbstractLeaderboard;
+use crate::leaderboards::rating::ratingtrait::RatingSystem;
+use crate::leaderboards::store::{checked::DEFAULT_BACKUPS, storetrait::LeaderboardStore};
+
+#[derive(Debug)]
+pub struct ConfiguredLeaderboard {
+    definition: LeaderboardDefinition,
+    store: Arc<dyn LeaderboardStore>,
+    state: HashMap<String, LeaderboardInnerState>,
+}
+
+impl ConfiguredLeaderboard {
+    pub fn new(definition: LeaderboardDefinition, store: Arc<dyn LeaderboardStore>) -> Self {
+        let mut out = Self {
+            definition,
+            store,
+            state: HashMap::new(),
+        };
+        out.read_initial_state();
+        out
+    }
+
+    pub fn definition(&self) -> &LeaderboardDefinition {
+        &self.definition
+    }
+}
+
+impl AbstractLeaderboard for ConfiguredLeaderboard {
+    fn get_name(&self) -> String {
+        self.definition.name.clone()
+    }
+
+    fn get_store(&self) -> Arc<dyn LeaderboardStore> {
+        self.store.clone()
+    }
+
+    fn backups(&self) -> usize {
+        self.definition.backups.unwrap_or(DEFAULT_BACKUPS)
+    }
+
+    fn history_streams(&self) -> Option<usize> {
+        self.definition.history_streams
+    }
+
+    fn get_k_factor(&self) -> f32 {
+        self.definition.k_factor()
+    }
+
+    fn __get_state(&mut self) -> &mut HashMap<String, LeaderboardInnerState> {
+        &mut self.state
+    }
+
+    fn calculate_score(&self, performance: &UserChatPerformance) -> Option<f32> {
+        self.definition.calculate_score(performance)
+    }
+
+    fn rating_system(&self) -> Box<dyn RatingSystem> {
+        self.definition.rating.build(self.get_k_factor())
+    }
+
+    fn absence_policy(&self) -> AbsencePolicy {
+        self.definition.absence
+    }
+
+    fn provisional_streams(&self) -> u32 {
+        self.definition.provisional_streams
+    }
+
+    fn inactive_after(&self) -> Option<u32> {
+        self.definition.inactive_after
+    }
+
+    fn explain(&self) -> bool {
+        self.definition.explain
+    }
+
+    fn export_formats(&self) -> Vec<ExportFormat> {
+        self.definition.formats.clone()
+    }
+
+    fn page_size(&self) -> usize {
+        self.definition.page_size.unwrap_or(DEFAULT_PAGE_SIZE)
+    }
+
+    fn explain_score(&self, performance: &UserChatPerformance) -> Vec<ScoreContribution> {
+        self.definition.score.contributions(&performance.metrics)
+    }
+}
@@ -0,0 +1,505 @@
+//! Declarative leaderboard definitions, loaded from a TOML file
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::_types::clptypes::{MessageTag, MetadataTypes, UserChatPerformance};
+use crate::is_message_origin;
+use crate::leaderboards::explain::ScoreContribution;
+use crate::leaderboards::export::ExportFormat;
+use crate::leaderboards::leaderboardtrait::K;
+use crate::leaderboards::rating::{
+    elo::Elo, glicko2::Glicko2, openskill::OpenSkill, ratingtrait::RatingSystem,
+};
+
+/// The leaderboard definitions that ship with this crate
+pub const DEFAULT_LEADERBOARDS: &str = include_str!("../../leaderboards.toml");
+
+/// A file of leaderboard definitions
+#[derive(Debug, Clone, Deserialize)]
+pub struct LeaderboardsConfig {
+    #[serde(rename = "leaderboard", default)]
+    pub leaderboards: Vec<LeaderboardDefinition>,
+}
+
+impl LeaderboardsConfig {
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Turns on explanations for every leaderboard
+    pub fn with_explanations(mut self) -> Self {
+        for definition in self.leaderboards.iter_mut() {
+            definition.explain = true;
+        }
+        self
+    }
+
+    /// Adds export formats to every leaderboard
+    pub fn with_formats(mut self, formats: &[ExportFormat]) -> Self {
+        for definition in self.leaderboards.iter_mut() {
+            for format in formats {
+                if !definition.formats.contains(format) {
+                    definition.formats.push(*format);
+                }
+            }
+        }
+        self
+    }
+
+    /// Sets the K-factor of the leaderboards that don't set their own
+    pub fn with_default_k(mut self, k: f32) -> Self {
+        for definition in self.leaderboards.iter_mut() {
+            definition.k.get_or_insert(k);
+        }
+        self
+    }
+}
+
+impl FromStr for LeaderboardsConfig {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config: LeaderboardsConfig = toml::from_str(s)?;
+        let mut names: Vec<&str> = config
+            .leaderboards
+            .iter()
+            .map(|definition| definition.name.as_str())
+            .collect();
+        names.sort();
+        if let Some(duplicate) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(format!("Leaderboard {} is defined more than once", duplicate[0]).into());
+        }
+        Ok(config)
+    }
+}
+
+impl Default for LeaderboardsConfig {
+    fn default() -> Self {
+        DEFAULT_LEADERBOARDS
+            .parse()
+            .expect("Default leaderboard definitions are valid")
+    }
+}
+
+/// Where a user must have chatted from to be on a leaderboard
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OriginFilter {
+    #[default]
+    Any,
+    Twitch,
+    Discord,
+    Emote,
+}
+
+impl OriginFilter {
+    fn matches(&self, performance: &UserChatPerformance) -> bool {
+        match self {
+            OriginFilter::Any => true,
+            OriginFilter::Twitch => is_message_origin!(performance, MessageTag::Twitch),
+            OriginFilter::Discord => is_message_origin!(performance, MessageTag::Discord),
+            OriginFilter::Emote => is_message_origin!(performance, MessageTag::Emote),
+        }
+    }
+}
+
+/// What happens to the elo of users who didn't chat in an update
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(tag = "policy", rename_all = "snake_case", deny_unknown_fields)]
+pub enum AbsencePolicy {
+    /// Their elo stays the same
+    #[default]
+    Freeze,
+    /// Their elo moves `rate` (0 to 1) of the way back to the initial elo,
+    /// once they have missed more than `after` updates in a row
+    Decay {
+        rate: f32,
+        #[serde(default)]
+        after: u32,
+    },
+    /// They lose against every sampled user
+    LostToEveryone,
+}
+
+/// The rating system a leaderboard uses, and its parameters
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(tag = "system", rename_all = "snake_case")]
+pub enum RatingConfig {
+    #[default]
+    Elo,
+    Glicko2(Glicko2),
+    #[serde(rename = "openskill")]
+    OpenSkill(OpenSkill),
+}
+
+impl RatingConfig {
+    /// Creates the rating system, with the K-factor used by Elo
+    pub fn build(&self, k: f32) -> Box<dyn RatingSystem> {
+        match self {
+            RatingConfig::Elo => Box::new(Elo { k }),
+            RatingConfig::Glicko2(glicko2) => Box::new(glicko2.clone()),
+            RatingConfig::OpenSkill(openskill) => Box::new(openskill.clone()),
+        }
+    }
+}
+
+/// A single leaderboard
+#[derive(Debug, Clone, Deserialize)]
+pub struct LeaderboardDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub origin: OriginFilter,
+    #[serde(default)]
+    pub special_role: Option<bool>,
+    #[serde(default)]
+    pub badge: Option<String>,
+    #[serde(default)]
+    pub without_badge: Option<String>,
+    pub score: ScoreExpression,
+    #[serde(default)]
+    pub k: Option<f32>,
+    #[serde(default)]
+    pub rating: RatingConfig,
+    #[serde(default)]
+    pub absence: AbsencePolicy,
+    /// Users who chatted in fewer updates than this are provisional
+    #[serde(default)]
+    pub provisional_streams: u32,
+    /// Users who missed this many updates in a row are inactive
+    #[serde(default)]
+    pub inactive_after: Option<u32>,
+    /// How many previous versions of the leaderboard to keep
+    #[serde(default)]
+    pub backups: Option<usize>,
+    /// Save an explanation of each update to `{name}.explain.json`
+    #[serde(default)]
+    pub explain: bool,
+    /// Other formats to export the leaderboard in, next to `{name}.bin`
+    #[serde(default)]
+    pub formats: Vec<ExportFormat>,
+    /// Users per page when exporting in pages
+    #[serde(default)]
+    pub page_size: Option<usize>,
+    /// How many streams to keep the history of. The whole season's is kept
+    /// unless this is set.
+    #[serde(default)]
+    pub history_streams: Option<usize>,
+}
+
+impl LeaderboardDefinition {
+    pub fn k_factor(&self) -> f32 {
+        self.k.unwrap_or(K)
+    }
+
+    /// Checks the performance against the filters, and scores it if it passes
+    pub fn calculate_score(&self, performance: &UserChatPerformance) -> Option<f32> {
+        if !self.origin.matches(performance) {
+            return None;
+        }
+
+        if let Some(special_role) = self.special_role {
+            let has_special_role = performance
+                .metadata
+                .get("special_role")
+                .and_then(|role| role.get_bool())
+                .copied()
+                .unwrap_or(false);
+            if has_special_role != special_role {
+                return None;
+            }
+        }
+
+        if self.badge.is_some() || self.without_badge.is_some() {
+            let has_badge = |description: &str| {
+                matches!(
+                    performance.metadata.get("badges"),
+                    Some(MetadataTypes::BadgeList(badge_list))
+                        if badge_list.iter().any(|badge| badge.description == description)
+                )
+            };
+            if self.badge.as_deref().is_some_and(|badge| !has_badge(badge)) {
+                return None;
+            }
+            if self.without_badge.as_deref().is_some_and(has_badge) {
+                return None;
+            }
+        }
+
+        Some(self.score.evaluate(&performance.metrics))
+    }
+}
+
+/// Which metric a score term refers to
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricReference {
+    /// The sum of every metric
+    All,
+    Named(String),
+}
+
+/// A linear combination of metrics, e.g. `text + 0.5 * bits`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ScoreExpression {
+    pub constant: f32,
+    pub terms: Vec<(MetricReference, f32)>,
+}
+
+#[derive(Debug)]
+pub struct ScoreExpressionError(String);
+
+impl fmt::Display for ScoreExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid score expression: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScoreExpressionError {}
+
+impl ScoreExpression {
+    pub fn evaluate(&self, metrics: &HashMap<String, f32>) -> f32 {
+        self.terms
+            .iter()
+            .map(|(metric, coefficient)| {
+                let value = match metric {
+                    MetricReference::All => {
+                        /* Sum in name order so the result doesn't depend on the map order */
+                        let mut names: Vec<&String> = metrics.keys().collect();
+                        names.sort();
+                        names.into_iter().map(|name| metrics[name]).sum()
+                    }
+                    MetricReference::Named(name) => *metrics.get(name).unwrap_or(&0.0),
+                };
+                value * coefficient
+            })
+            .fold(self.constant, |total, value| total + value)
+    }
+
+    /// How much each metric, and the constant, adds to the score
+    pub fn contributions(&self, metrics: &HashMap<String, f32>) -> Vec<ScoreContribution> {
+        let mut names: Vec<&String> = metrics.keys().collect();
+        names.sort();
+
+        let mut contributions: Vec<ScoreContribution> = Vec::new();
+        let mut add = |metric: &str, value: f32| match contributions
+            .iter_mut()
+            .find(|c| c.metric == metric)
+        {
+            Some(contribution) => contribution.value += value,
+            None => contributions.push(ScoreContribution {
+                metric: metric.to_string(),
+                value,
+            }),
+        };
+        for (metric, coefficient) in self.terms.iter() {
+            match metric {
+                MetricReference::All => names
+                    .iter()
+                    .for_each(|name| add(name, metrics[*name] * coefficient)),
+                MetricReference::Named(name) => {
+                    add(name, *metrics.get(name).unwrap_or(&0.0) * coefficient)
+                }
+            }
+        }
+        if self.constant != 0.0 {
+            add("constant", self.constant);
+        }
+        contributions
+    }
+
+    /// Whether the term so far ends in a number's exponent, like `1e`, so a
+    /// following sign belongs to the number
+    fn in_exponent(term: &str) -> bool {
+        let factor = term.rsplit('*').next().unwrap_or_default().trim();
+        (factor.ends_with('e') || factor.ends_with('E'))
+            && format!("{}0", factor).parse::<f32>().is_ok()
+    }
+
+    /// Parses a single term, which is a product of numbers and at most one metric
+    fn parse_term(term: &str) -> Result<(Option<MetricReference>, f32), ScoreExpressionError> {
+        let mut metric = None;
+        let mut coefficient = 1.0;
+        for factor in term.split('*').map(str::trim) {
+            if factor.is_empty() {
+                return Err(ScoreExpressionError(format!(
+                    "empty factor in \"{}\"",
+                    term
+                )));
+            }
+            if let Ok(number) = factor.parse::<f32>() {
+                coefficient *= number;
+                continue;
+            }
+            if !factor
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                return Err(ScoreExpressionError(format!(
+                    "unknown factor \"{}\"",
+                    factor
+                )));
+            }
+            if metric.is_some() {
+                return Err(ScoreExpressionError(format!(
+                    "\"{}\" multiplies two metrics together",
+                    term
+                )));
+            }
+            metric = Some(match factor {
+                "all" => MetricReference::All,
+                name => MetricReference::Named(name.to_string()),
+            });
+        }
+        Ok((metric, coefficient))
+    }
+}
+
+impl FromStr for ScoreExpression {
+    type Err = ScoreExpressionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Split into signed terms, keeping the sign with each term
+        let mut signed_terms: Vec<(f32, String)> = vec![];
+        let mut sign = 1.0;
+        let mut current = String::new();
+        for c in s.chars() {
+            match c {
+                '+' | '-' if Self::in_exponent(&current) => current.push(c),
+                '+' | '-' if current.trim().is_empty() || current.trim_end().ends_with('*') => {
+                    // Unary sign
+                    if c == '-' {
+                        sign = -sign;
+                    }
+                }
+                '+' | '-' => {
+                    signed_terms.push((sign, std::mem::take(&mut current)));
+                    sign = if c == '-' { -1.0 } else { 1.0 };
+                }
+                c => current.push(c),
+            }
+        }
+        signed_terms.push((sign, current));
+
+        let mut expression = ScoreExpression::default();
+        for (sign, term) in signed_terms {
+            if term.trim().is_empty() {
+                return Err(ScoreExpressionError(format!("empty term in \"{}\"", s)));
+            }
+            match Self::parse_term(&term)? {
+                (Some(metric), coefficient) => expression.terms.push((metric, sign * coefficient)),
+                (None, coefficient) => expression.constant += sign * coefficient,
+            }
+        }
+        Ok(expression)
+    }
+}
+
+impl TryFrom<String> for ScoreExpression {
+    type Error = ScoreExpressionError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(values: &[(&str, f32)]) -> HashMap<String, f32> {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect()
+    }
+
+    fn score(expression: &str, values: &[(&str, f32)]) -> f32 {
+        expression
+            .parse::<ScoreExpression>()
+            .unwrap()
+            .evaluate(&metrics(values))
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        let values = [("text", 3.0), ("bits", 10.0), ("subs", 4.0)];
+
+        assert_eq!(score("text + 0.5 * bits - subs * 2", &values), 0.0);
+        assert_eq!(score("2 * text * 3 + 1", &values), 19.0);
+        assert_eq!(score("-text + -2 * -bits", &values), 17.0);
+    }
+
+    #[test]
+    fn numbers_can_have_exponents() {
+        let values = [("bits", 1000.0)];
+
+        assert_eq!(score("1e-3 * bits", &values), 1.0);
+        assert_eq!(score("bits * 2E+1 - 1e3", &values), 19000.0);
+        assert_eq!(score("1.5e2", &values), 150.0);
+    }
+
+    #[test]
+    fn all_sums_every_metric() {
+        let expression: ScoreExpression = "all - text".parse().unwrap();
+
+        assert_eq!(expression.terms[0], (MetricReference::All, 1.0));
+        assert_eq!(
+            expression.evaluate(&metrics(&[("text", 3.0), ("bits", 10.0)])),
+            10.0
+        );
+    }
+
+    #[test]
+    fn unknown_metrics_score_nothing() {
+        /* Metrics can be registered at run time, so any name parses */
+        let expression: ScoreExpression = "custom * 2 + 1".parse().unwrap();
+
+        assert_eq!(
+            expression.terms,
+            vec![(MetricReference::Named("custom".to_string()), 2.0)]
+        );
+        assert_eq!(expression.evaluate(&metrics(&[("text", 3.0)])), 1.0);
+    }
+
+    #[test]
+    fn malformed_expressions_are_errors() {
+        for expression in [
+            "",
+            "text +",
+            "text + + ",
+            "* text",
+            "text * ",
+            "text * bits",
+            "text / 2",
+            "(text)",
+        ] {
+            assert!(
+                expression.parse::<ScoreExpression>().is_err(),
+                "{:?} parsed",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn the_shipped_leaderboards_are_valid() {
+        let config = LeaderboardsConfig::default();
+
+        assert!(!config.leaderboards.is_empty());
+        for definition in config.leaderboards {
+            for (metric, _) in definition.score.terms {
+                if let MetricReference::Named(name) = metric {
+                    assert!(
+                        crate::metrics::BUILTIN_METRICS.contains(&name.as_str()),
+                        "{} scores unknown metric {}",
+                        definition.name,
+                        name
+                    );
+                }
+            }
+        }
+    }
+}
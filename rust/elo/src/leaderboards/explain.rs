@@ -0,0 +1,148 @@
+//! Explanations of how a leaderboard update came about, saved next to the
+//! leaderboard as `{name}.explain.json`
+use log::info;
+use serde::Serialize;
+
+use crate::leaderboards::store::storetrait::LeaderboardStore;
+
+/// One of the sampled users every user is compared against
+#[derive(Debug, Clone, Serialize)]
+pub struct SampledOpponent {
+    pub score: f32,
+    pub elo: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deviation: Option<f32>,
+}
+
+/// How much a metric added to a user's score
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreContribution {
+    pub metric: String,
+    pub value: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UserExplanation {
+    pub id: String,
+    pub username: String,
+    pub score: f32,
+    /// Whether the user chatted. Users who didn't are rated by the
+    /// leaderboard's absence policy
+    pub participated: bool,
+    /// Empty if the user has no performance in this update
+    pub contributions: Vec<ScoreContribution>,
+    /// Opponents the user scored higher than
+    pub wins: u32,
+    /// Opponents the user scored the same as or lower than
+    pub losses: u32,
+    /// Sum of the chances of beating each opponent, going by rating
+    pub expected_wins: f32,
+    pub elo_before: f32,
+    pub elo_after: f32,
+    /// `elo_after - elo_before`, which for Elo is `k * (wins - expected_wins)`
+    pub elo_delta: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deviation_before: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deviation_after: Option<f32>,
+    pub rank: u32,
+    pub previous_rank: Option<u32>,
+}
+
+/// Explains a leaderboard update.
+///
+/// Every user is compared against the same `opponents`. A user wins
+/// against an opponent with a lower score. For Elo, they are expected
+/// to win with probability `1 / (1 + 10^((opponent.elo - elo_before) / 400))`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LeaderboardExplanation {
+    pub leaderboard: String,
+    pub rating_system: String,
+    pub k: f32,
+    pub opponents: Vec<SampledOpponent>,
+    /// Sorted by rank
+    pub users: Vec<UserExplanation>,
+}
+
+impl LeaderboardExplanation {
+    pub fn save(&self, store: &dyn LeaderboardStore) -> Result<(), Box<dyn std::error::Error>> {
+        let key = format!("{}.explain.json", self.leaderboard);
+        store.write(&key, serde_json::to_string_pretty(self)?.as_bytes())?;
+        info!(
+            "{} leaderboard explanation saved to {}",
+            self.leaderboard, key
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use serde_json::Value;
+
+    use crate::_types::clptypes::UserChatPerformance;
+    use crate::leaderboards::configured::ConfiguredLeaderboard;
+    use crate::leaderboards::definition::LeaderboardsConfig;
+    use crate::leaderboards::header::RunInfo;
+    use crate::leaderboards::leaderboardtrait::AbstractLeaderboard;
+    use crate::leaderboards::store::memory::MemoryStore;
+    use crate::leaderboards::store::storetrait::LeaderboardStore;
+
+    fn performance(id: &str, text: f32, bits: f32) -> UserChatPerformance {
+        UserChatPerformance {
+            id: id.to_string(),
+            username: id.to_string(),
+            avatar: String::new(),
+            metrics: HashMap::from([("text".to_string(), text), ("bits".to_string(), bits)]),
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn explanations_break_scores_down_by_metric() {
+        let store = Arc::new(MemoryStore::new());
+        let config: LeaderboardsConfig =
+            "[[leaderboard]]\nname = \"test\"\nscore = \"text + 2 * bits + 1\"\nexplain = true"
+                .parse()
+                .unwrap();
+        let mut leaderboard =
+            ConfiguredLeaderboard::new(config.leaderboards[0].clone(), store.clone());
+        leaderboard.update_leaderboard(performance("a", 3.0, 5.0));
+        leaderboard.update_leaderboard(performance("b", 1.0, 0.0));
+        leaderboard.save(&RunInfo::default());
+
+        let saved = store.read("test.explain.json").unwrap().unwrap();
+        let explanation: Value = serde_json::from_slice(&saved).unwrap();
+        assert_eq!(explanation["leaderboard"], "test");
+        let users = explanation["users"].as_array().unwrap();
+        assert_eq!(users.len(), 2);
+
+        let a = &users[0];
+        assert_eq!(a["id"], "a");
+        assert_eq!(a["score"], 14.0);
+        assert_eq!(a["rank"], 1);
+        let contributions: Vec<(&str, f64)> = a["contributions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| (c["metric"].as_str().unwrap(), c["value"].as_f64().unwrap()))
+            .collect();
+        assert_eq!(
+            contributions,
+            [("text", 3.0), ("bits", 10.0), ("constant", 1.0)]
+        );
+
+        let opponents = explanation["opponents"].as_array().unwrap().len() as f64;
+        for user in users {
+            let number = |field: &str| user[field].as_f64().unwrap();
+            assert_eq!(number("wins") + number("losses"), opponents);
+            assert_eq!(
+                number("elo_delta"),
+                2.0 * (number("wins") - number("expected_wins"))
+            );
+        }
+    }
+}
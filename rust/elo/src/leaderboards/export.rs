@@ -0,0 +1,207 @@
+//! Other formats a leaderboard can be exported in, next to `{name}.bin`:
+//!
+//! - `json`: `{name}.json`, the same items as pretty JSON
+//! - `csv`: `{name}.csv`, one row per user for spreadsheets
+//! - `pages`: `pages/{name}/{page}.bin`, the items split into pages of
+//!   `LeaderboardExport`, with `pages/{name}/index.bin` saying which page
+//!   every user is on. The site can then load a single page of a big
+//!   leaderboard.
+use std::collections::BTreeMap;
+
+use log::info;
+use prost::Message;
+use serde::Deserialize;
+
+use crate::_types::leaderboardtypes::{LeaderboardExport, LeaderboardPageIndex};
+use crate::leaderboards::store::storetrait::{join_key, LeaderboardStore};
+
+/// Users per page, unless the leaderboard sets its own
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Json,
+    Csv,
+    Pages,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            "pages" => Ok(ExportFormat::Pages),
+            other => Err(format!("Unknown export format {:?}", other).into()),
+        }
+    }
+}
+
+/// Where the pages of a leaderboard are kept in its store
+pub fn pages_directory(name: &str) -> String {
+    join_key("pages", name)
+}
+
+/// Saves a leaderboard in the other formats. The items must be sorted by
+/// rank.
+pub fn write_exports(
+    store: &dyn LeaderboardStore,
+    name: &str,
+    export: &LeaderboardExport,
+    formats: &[ExportFormat],
+    page_size: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for format in formats {
+        match format {
+            ExportFormat::Json => {
+                let key = format!("{}.json", name);
+                store.write(&key, serde_json::to_string_pretty(export)?.as_bytes())?;
+                info!("{} leaderboard exported to {}", name, key);
+            }
+            ExportFormat::Csv => {
+                let key = format!("{}.csv", name);
+                store.write(&key, &to_csv(export)?)?;
+                info!("{} leaderboard exported to {}", name, key);
+            }
+            ExportFormat::Pages => {
+                write_pages(store, name, export, page_size)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn to_csv(export: &LeaderboardExport) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "rank",
+        "id",
+        "username",
+        "elo",
+        "delta",
+        "deviation",
+        "volatility",
+        "rated_streams",
+        "missed_streams",
+        "provisional",
+        "inactive",
+        "badges",
+    ])?;
+    let optional = |value: Option<f32>| value.map(|value| value.to_string()).unwrap_or_default();
+    for item in export.items.iter() {
+        let badges: Vec<&str> = item
+            .badges
+            .iter()
+            .map(|badge| badge.description.as_str())
+            .collect();
+        writer.write_record([
+            item.rank.to_string(),
+            item.id.clone(),
+            item.username.clone(),
+            item.elo.to_string(),
+            item.delta.to_string(),
+            optional(item.deviation),
+            optional(item.volatility),
+            item.rated_streams
+                .map(|streams| streams.to_string())
+                .unwrap_or_default(),
+            item.missed_streams.to_string(),
+            item.provisional.to_string(),
+            item.inactive.to_string(),
+            badges.join(";"),
+        ])?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+fn write_pages(
+    store: &dyn LeaderboardStore,
+    name: &str,
+    export: &LeaderboardExport,
+    page_size: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let page_size = page_size.max(1);
+    let directory = pages_directory(name);
+    let mut user_pages = BTreeMap::new();
+    let mut page_count = 0;
+    for (index, items) in export.items.chunks(page_size).enumerate() {
+        let page = index as u32 + 1;
+        for item in items {
+            user_pages.insert(item.id.clone(), page);
+        }
+        let page_export = LeaderboardExport {
+            items: items.to_vec(),
+            header: export.header.clone(),
+        };
+        store.write(
+            &join_key(&directory, &format!("{}.bin", page)),
+            &page_export.encode_to_vec(),
+        )?;
+        page_count = page;
+    }
+
+    /* Delete pages left over from when the leaderboard was bigger, so
+    they can't be mistaken for current ones */
+    for key in store.list(&directory)? {
+        let number = key
+            .rsplit('/')
+            .next()
+            .and_then(|file| file.strip_suffix(".bin"))
+            .and_then(|page| page.parse::<u32>().ok());
+        if number.is_some_and(|number| number > page_count) {
+            store.remove(&key)?;
+        }
+    }
+
+    let index = LeaderboardPageIndex {
+        page_size: page_size as u32,
+        page_count,
+        total_items: export.items.len() as u32,
+        user_pages,
+    };
+    store.write(&join_key(&directory, "index.bin"), &index.encode_to_vec())?;
+    info!(
+        "{} leaderboard exported to {} pages in {}",
+        name, page_count, directory
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::_types::leaderboardtypes::LeaderboardExportItem;
+    use crate::leaderboards::store::memory::MemoryStore;
+
+    fn export(users: usize) -> LeaderboardExport {
+        LeaderboardExport {
+            items: (0..users)
+                .map(|user| LeaderboardExportItem {
+                    id: user.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            header: None,
+        }
+    }
+
+    #[test]
+    fn pages_beyond_the_page_count_are_deleted() {
+        let store = MemoryStore::new();
+        write_pages(&store, "test", &export(5), 2).unwrap();
+        assert_eq!(store.list(&pages_directory("test")).unwrap().len(), 4);
+
+        write_pages(&store, "test", &export(3), 2).unwrap();
+
+        assert_eq!(
+            store.list(&pages_directory("test")).unwrap(),
+            [
+                "pages/test/1.bin",
+                "pages/test/2.bin",
+                "pages/test/index.bin"
+            ]
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! The header saved with every leaderboard, recording the run that
+//! generated it
+use chrono::{DateTime, FixedOffset};
+use prost_types::Timestamp;
+use twitch_utils::datasource::VodInfo;
+
+use crate::_types::leaderboardtypes::LeaderboardExportHeader;
+
+/// Version of this crate, saved in the header of every leaderboard
+pub const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What a leaderboard update was generated from
+#[derive(Debug, Clone, Default)]
+pub struct RunInfo {
+    /// The stream the performances are from. Leaderboards also save a
+    /// snapshot to their history when it's set
+    pub stream: Option<VodInfo>,
+    /// The time range Discord messages were read from, if any were
+    pub discord_range: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>,
+    pub season: Option<String>,
+    /// Hash of the configs the performances were scored with
+    pub config_hash: Option<String>,
+    /// Messages read for the update, from every source
+    pub messages: u64,
+}
+
+impl RunInfo {
+    pub fn new(stream: VodInfo) -> Self {
+        Self {
+            stream: Some(stream),
+            ..Default::default()
+        }
+    }
+
+    /// When the chat of the run ended: the end of the stream, or of the
+    /// Discord messages without one. Unlike the time the run happened,
+    /// it's the same for every run over the same chat.
+    pub fn stream_end(&self) -> Option<Timestamp> {
+        self.stream
+            .as_ref()
+            .map(|stream| stream.end)
+            .or(self.discord_range.map(|(_, end)| end))
+            .map(|end| timestamp(&end))
+    }
+}
+
+pub(crate) fn timestamp(time: &DateTime<FixedOffset>) -> Timestamp {
+    Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
+impl LeaderboardExportHeader {
+    /// The header of a leaderboard updated in a run, that `participants`
+    /// users chatted in
+    pub fn new(run: &RunInfo, participants: u32) -> Self {
+        LeaderboardExportHeader {
+            stream_end: run.stream_end(),
+            vod_ids: run.stream.iter().map(|stream| stream.id.clone()).collect(),
+            discord_start: run.discord_range.map(|(start, _)| timestamp(&start)),
+            discord_end: run.discord_range.map(|(_, end)| timestamp(&end)),
+            season: run.season.clone().unwrap_or_default(),
+            tool_version: TOOL_VERSION.to_string(),
+            config_hash: run.config_hash.clone().unwrap_or_default(),
+            participants,
+            messages: run.messages,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_of_the_same_stream_are_identical() {
+        let start = DateTime::parse_from_rfc3339("2024-05-01T18:00:00+00:00").unwrap();
+        let run = RunInfo::new(VodInfo {
+            id: "1".to_string(),
+            start,
+            end: start + chrono::Duration::hours(4),
+        });
+
+        let header = LeaderboardExportHeader::new(&run, 10);
+
+        assert_eq!(header, LeaderboardExportHeader::new(&run, 10));
+        assert_eq!(
+            header.stream_end.unwrap().seconds,
+            start.timestamp() + 4 * 3600
+        );
+    }
+
+    #[test]
+    fn discord_only_runs_end_with_their_last_message() {
+        let start = DateTime::parse_from_rfc3339("2024-05-01T18:00:00+00:00").unwrap();
+        let end = start + chrono::Duration::days(7);
+        let run = RunInfo {
+            discord_range: Some((start, end)),
+            ..RunInfo::default()
+        };
+
+        let header = LeaderboardExportHeader::new(&run, 10);
+
+        assert_eq!(header.stream_end.unwrap().seconds, end.timestamp());
+    }
+}
@@ -0,0 +1,307 @@
+//! Snapshots of a leaderboard after every stream, kept under
+//! `history/{name}/` next to the leaderboard.
+//!
+//! A snapshot holds every ranked user, including those who didn't chat,
+//! as their rank can change while they sit a stream out. The snapshots are
+//! also collected into `{name}.history.bin`, which the site uses to draw
+//! how a user's rating and rank changed over the season. A leaderboard can
+//! set `history_streams` to only keep the snapshots of its last streams.
+use std::collections::HashMap;
+use std::path::Path;
+
+use log::{info, warn};
+use prost::Message;
+use prost_types::Timestamp;
+use twitch_utils::datasource::VodInfo;
+
+use crate::_types::leaderboardtypes::{
+    LeaderboardExportItem, LeaderboardHistory, LeaderboardSnapshot, LeaderboardSnapshotItem,
+};
+use crate::leaderboards::header::timestamp;
+use crate::leaderboards::store::storetrait::{join_key, LeaderboardStore};
+
+/// Where the snapshots of a leaderboard are kept in its store
+pub fn snapshot_directory(name: &str) -> String {
+    join_key("history", name)
+}
+
+/// Where the snapshots of a leaderboard are collected in its store
+pub fn history_key(name: &str) -> String {
+    format!("{}.history.bin", name)
+}
+
+/// Whether a file is a leaderboard's collected history rather than the
+/// leaderboard itself
+pub fn is_history_path(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".history.bin")
+}
+
+/// A user's place on a leaderboard after a stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryPoint {
+    pub vod_id: String,
+    pub stream_start: Option<Timestamp>,
+    pub rank: u32,
+    pub elo: f32,
+    pub deviation: Option<f32>,
+    pub participated: bool,
+}
+
+impl LeaderboardSnapshot {
+    /// Snapshots the ranked items of a leaderboard after a stream
+    pub fn new<'a>(
+        stream: &VodInfo,
+        items: impl IntoIterator<Item = &'a LeaderboardExportItem>,
+        participated: impl Fn(&str) -> bool,
+    ) -> Self {
+        LeaderboardSnapshot {
+            vod_id: stream.id.clone(),
+            stream_start: Some(Timestamp {
+                seconds: stream.start.timestamp(),
+                nanos: 0,
+            }),
+            stream_end: Some(timestamp(&stream.end)),
+            items: items
+                .into_iter()
+                .map(|item| LeaderboardSnapshotItem {
+                    id: item.id.clone(),
+                    rank: item.rank,
+                    elo: item.elo,
+                    deviation: item.deviation,
+                    participated: participated(&item.id),
+                })
+                .collect(),
+        }
+    }
+
+    fn stream_start_seconds(&self) -> i64 {
+        self.stream_start
+            .as_ref()
+            .map(|start| start.seconds)
+            .unwrap_or_default()
+    }
+
+    /// Saves the snapshot next to the leaderboard, replacing any earlier
+    /// snapshot of the same stream, and adds it to `{name}.history.bin`.
+    /// If `streams` is set, snapshots beyond the last `streams` streams are
+    /// dropped.
+    pub fn save(
+        &self,
+        store: &dyn LeaderboardStore,
+        name: &str,
+        streams: Option<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let key = snapshot_key(name, self.stream_start_seconds(), &self.vod_id);
+        store.write(&key, &self.encode_to_vec())?;
+        info!("{} leaderboard snapshot saved to {}", name, key);
+
+        /* Adding to the collected history instead of rebuilding it keeps
+        a save from reading every snapshot */
+        let mut history = match store.read(&history_key(name))? {
+            Some(data) => LeaderboardHistory::decode(&*data).or_else(|e| {
+                warn!("{} is broken ({}), rebuilding it", history_key(name), e);
+                LeaderboardHistory::load(store, name)
+            })?,
+            None => LeaderboardHistory::load(store, name)?,
+        };
+        history
+            .snapshots
+            .retain(|snapshot| snapshot.vod_id != self.vod_id);
+        history.snapshots.push(self.clone());
+        history.sort();
+        if let Some(streams) = streams {
+            history.compact(store, name, streams)?;
+        }
+        store.write(&history_key(name), &history.encode_to_vec())?;
+        Ok(())
+    }
+
+    /// The snapshot of a leaderboard after a stream, if one was saved
+    pub fn load(
+        store: &dyn LeaderboardStore,
+        name: &str,
+        stream: &VodInfo,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let key = snapshot_key(name, stream.start.timestamp(), &stream.id);
+        match store.read(&key)? {
+            Some(data) => Ok(Some(LeaderboardSnapshot::decode(&*data)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn snapshot_key(name: &str, stream_start_seconds: i64, vod_id: &str) -> String {
+    join_key(
+        &snapshot_directory(name),
+        &format!("{}-{}.bin", stream_start_seconds, vod_id),
+    )
+}
+
+impl LeaderboardHistory {
+    /// Loads every snapshot of a leaderboard, oldest stream first
+    pub fn load(
+        store: &dyn LeaderboardStore,
+        name: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut history = LeaderboardHistory {
+            snapshots: Vec::new(),
+        };
+        for key in store.list(&snapshot_directory(name))? {
+            if !key.ends_with(".bin") {
+                continue;
+            }
+            if let Some(data) = store.read(&key)? {
+                history.snapshots.push(LeaderboardSnapshot::decode(&*data)?);
+            }
+        }
+        history.sort();
+        Ok(history)
+    }
+
+    fn sort(&mut self) {
+        self.snapshots.sort_by(|a, b| {
+            a.stream_start_seconds()
+                .cmp(&b.stream_start_seconds())
+                .then_with(|| a.vod_id.cmp(&b.vod_id))
+        });
+    }
+
+    /// Drops the snapshots of all but the last `streams` streams, along
+    /// with their files
+    fn compact(
+        &mut self,
+        store: &dyn LeaderboardStore,
+        name: &str,
+        streams: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let dropped = self.snapshots.len().saturating_sub(streams);
+        for snapshot in self.snapshots.drain(..dropped) {
+            let key = snapshot_key(name, snapshot.stream_start_seconds(), &snapshot.vod_id);
+            store.remove(&key)?;
+        }
+        Ok(())
+    }
+
+    /// How a user did after every stream they were ranked in
+    pub fn user_series(&self, id: &str) -> Vec<HistoryPoint> {
+        self.snapshots
+            .iter()
+            .filter_map(|snapshot| {
+                let item = snapshot.items.iter().find(|item| item.id == id)?;
+                Some(history_point(snapshot, item))
+            })
+            .collect()
+    }
+
+    /// The series of every user, keyed by user id
+    pub fn series(&self) -> HashMap<String, Vec<HistoryPoint>> {
+        let mut series: HashMap<String, Vec<HistoryPoint>> = HashMap::new();
+        for snapshot in self.snapshots.iter() {
+            for item in snapshot.items.iter() {
+                series
+                    .entry(item.id.clone())
+                    .or_default()
+                    .push(history_point(snapshot, item));
+            }
+        }
+        series
+    }
+}
+
+fn history_point(snapshot: &LeaderboardSnapshot, item: &LeaderboardSnapshotItem) -> HistoryPoint {
+    HistoryPoint {
+        vod_id: snapshot.vod_id.clone(),
+        stream_start: snapshot.stream_start,
+        rank: item.rank,
+        elo: item.elo,
+        deviation: item.deviation,
+        participated: item.participated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+    use crate::leaderboards::store::memory::MemoryStore;
+
+    fn stream(id: &str, start: i64) -> VodInfo {
+        let start = DateTime::from_timestamp(start, 0).unwrap().fixed_offset();
+        VodInfo {
+            id: id.to_string(),
+            start,
+            end: start,
+        }
+    }
+
+    fn item(id: &str, elo: f32) -> LeaderboardExportItem {
+        LeaderboardExportItem {
+            id: id.to_string(),
+            elo,
+            ..Default::default()
+        }
+    }
+
+    fn saved_history(store: &MemoryStore) -> LeaderboardHistory {
+        let data = store.read(&history_key("test")).unwrap().unwrap();
+        LeaderboardHistory::decode(&*data).unwrap()
+    }
+
+    #[test]
+    fn only_the_last_streams_are_kept() {
+        let store = MemoryStore::new();
+        let items = [item("a", 1210.0), item("b", 1190.0)];
+        for (i, id) in ["1", "2", "3"].iter().enumerate() {
+            let snapshot =
+                LeaderboardSnapshot::new(&stream(id, i as i64 * 100), &items[i % 2..], |_| true);
+            snapshot.save(&store, "test", Some(2)).unwrap();
+        }
+
+        let history = saved_history(&store);
+        let vods: Vec<&str> = history
+            .snapshots
+            .iter()
+            .map(|s| s.vod_id.as_str())
+            .collect();
+        assert_eq!(vods, ["2", "3"]);
+        assert_eq!(history.snapshots[0].items.len(), 1);
+        assert_eq!(history.user_series("a").len(), 1);
+        assert!(LeaderboardSnapshot::load(&store, "test", &stream("1", 0))
+            .unwrap()
+            .is_none());
+        assert_eq!(store.list(&snapshot_directory("test")).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn every_stream_is_kept_unless_limited() {
+        let store = MemoryStore::new();
+        let items = [item("a", 1210.0)];
+        for i in 0..150 {
+            LeaderboardSnapshot::new(&stream(&i.to_string(), i * 100), &items, |_| true)
+                .save(&store, "test", None)
+                .unwrap();
+        }
+
+        assert_eq!(saved_history(&store).user_series("a").len(), 150);
+        assert_eq!(store.list(&snapshot_directory("test")).unwrap().len(), 150);
+    }
+
+    #[test]
+    fn a_broken_history_is_rebuilt_from_the_snapshots() {
+        let store = MemoryStore::new();
+        let items = [item("a", 1210.0)];
+        LeaderboardSnapshot::new(&stream("1", 0), &items, |_| true)
+            .save(&store, "test", None)
+            .unwrap();
+        store.write(&history_key("test"), b"not a history").unwrap();
+
+        LeaderboardSnapshot::new(&stream("2", 100), &items, |_| false)
+            .save(&store, "test", None)
+            .unwrap();
+
+        let history = saved_history(&store);
+        assert_eq!(history.snapshots.len(), 2);
+        assert!(!history.user_series("a")[1].participated);
+    }
+}
@@ -1,45 +1,154 @@
 use crate::_types::{
     clptypes::UserChatPerformance,
     leaderboardtypes::{
-        export_item_to_inner_state, BadgeInformation, LeaderboardExport, LeaderboardExportItem,
-        LeaderboardInnerState,
+        export_item_to_inner_state, BadgeInformation, LeaderboardExport, LeaderboardExportHeader,
+        LeaderboardExportItem, LeaderboardInnerState, LeaderboardSnapshot,
     },
 };
-use log::{debug, info, warn};
+use crate::leaderboards::definition::AbsencePolicy;
+use crate::leaderboards::explain::{
+    LeaderboardExplanation, SampledOpponent, ScoreContribution, UserExplanation,
+};
+use crate::leaderboards::export::{write_exports, ExportFormat, DEFAULT_PAGE_SIZE};
+use crate::leaderboards::header::RunInfo;
+use crate::leaderboards::quarantine::{quarantine, QuarantinedUser};
+use crate::leaderboards::rating::elo::Elo;
+use crate::leaderboards::rating::ratingtrait::{Opponents, Rating, RatingSystem};
+use crate::leaderboards::store::{
+    checked::{broken_key, read_checked, set_aside, write_checked, DEFAULT_BACKUPS},
+    filesystem::FileStore,
+    storetrait::LeaderboardStore,
+};
+use log::{debug, error, info, warn};
 use prost::Message;
-use std::collections::HashMap;
-use std::io::{Read, Write};
-use std::{fs, fs::File};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
-const K: f32 = 2.0;
+/// K-factor used when a leaderboard doesn't set one
+pub const K: f32 = 2.0;
 
-pub trait AbstractLeaderboard {
-    fn new() -> Self
-    where
-        Self: Sized;
+/// Elo new users start with
+pub const INITIAL_ELO: f32 = 1200.0;
 
+pub trait AbstractLeaderboard {
     fn get_name(&self) -> String;
 
+    /// Where the leaderboard is kept
+    fn get_store(&self) -> Arc<dyn LeaderboardStore> {
+        Arc::new(FileStore::default())
+    }
+
+    /// The key of the leaderboard in its store
+    fn get_key(&self) -> String {
+        format!("{}.bin", self.get_name())
+    }
+
+    /// How many previous versions of the leaderboard to keep
+    fn backups(&self) -> usize {
+        DEFAULT_BACKUPS
+    }
+
+    /// How many streams to keep the history of, if not the whole season
+    fn history_streams(&self) -> Option<usize> {
+        None
+    }
+
+    /// The K-factor used when updating the elo
+    fn get_k_factor(&self) -> f32 {
+        K
+    }
+
+    /// The rating system used to update the ratings
+    fn rating_system(&self) -> Box<dyn RatingSystem> {
+        Box::new(Elo {
+            k: self.get_k_factor(),
+        })
+    }
+
     fn __get_state(&mut self) -> &mut HashMap<String, LeaderboardInnerState>;
 
     fn calculate_score(&self, performance: &UserChatPerformance) -> Option<f32>;
 
-    fn read_initial_state(&mut self) {
-        info!("Loading {} leaderboard...", self.get_name());
-        let path = format!("{}.bin", self.get_name());
-        if !std::path::Path::new(&path).exists() {
-            info!("{} leaderboard doesn't already exist.", self.get_name());
-            return;
-        }
+    /// What happens to users on the leaderboard who didn't chat
+    fn absence_policy(&self) -> AbsencePolicy {
+        AbsencePolicy::Freeze
+    }
+
+    /// Users who chatted in fewer updates than this are exported as
+    /// provisional
+    fn provisional_streams(&self) -> u32 {
+        0
+    }
+
+    /// Users who missed this many updates in a row are exported as inactive
+    fn inactive_after(&self) -> Option<u32> {
+        None
+    }
+
+    /// Whether to save an explanation of each update next to the leaderboard
+    fn explain(&self) -> bool {
+        false
+    }
+
+    /// Other formats to export the leaderboard in when it's saved
+    fn export_formats(&self) -> Vec<ExportFormat> {
+        Vec::new()
+    }
+
+    /// Users per page when exporting the leaderboard in pages
+    fn page_size(&self) -> usize {
+        DEFAULT_PAGE_SIZE
+    }
 
-        let mut file = File::open(path).unwrap();
+    /// How much each metric added to the score of a performance, used
+    /// when explaining updates
+    fn explain_score(&self, _performance: &UserChatPerformance) -> Vec<ScoreContribution> {
+        Vec::new()
+    }
 
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf).unwrap();
+    fn read_initial_state(&mut self) {
+        info!("Loading {} leaderboard...", self.get_name());
+        let buf = match read_checked(
+            self.get_store().as_ref(),
+            &self.get_key(),
+            self.backups(),
+            |data| LeaderboardExport::decode(data).is_ok(),
+        ) {
+            Ok(Some(buf)) => buf,
+            Ok(None) => {
+                info!("{} leaderboard doesn't already exist.", self.get_name());
+                return;
+            }
+            /* Whatever is left of it is moved out of the way before starting
+            over, so saving doesn't overwrite it */
+            Err(e) => {
+                error!(
+                    "Could not load the {} leaderboard: {}. STARTING IT OVER, \
+                    the broken files are kept in {}",
+                    self.get_name(),
+                    e,
+                    broken_key(&self.get_key())
+                );
+                if let Err(e) =
+                    set_aside(self.get_store().as_ref(), &self.get_key(), self.backups())
+                {
+                    error!(
+                        "Could not set aside the {} leaderboard: {}",
+                        self.get_name(),
+                        e
+                    );
+                }
+                return;
+            }
+        };
 
         let leaderboard = LeaderboardExport::decode(&*buf).unwrap();
 
-        for item in leaderboard.items {
+        /* Users saved before rated streams were counted have been on the
+        leaderboard all along, so they don't start over as provisional */
+        let legacy_rated_streams = self.provisional_streams();
+        for mut item in leaderboard.items {
+            item.rated_streams.get_or_insert(legacy_rated_streams);
             self.__get_state()
                 .insert(item.id.clone(), export_item_to_inner_state(item));
         }
@@ -55,6 +164,18 @@ pub trait AbstractLeaderboard {
         );
         if let Some(score) = self.calculate_score(&performance) {
             debug!("Score for the above is {}", score);
+            if !score.is_finite() {
+                warn!(
+                    "Ignoring the {} performance of {} ({}), scored {}",
+                    self.get_name(),
+                    performance.username,
+                    performance.id,
+                    score
+                );
+                return;
+            }
+            let contributions = self.explain().then(|| self.explain_score(&performance));
+            let initial_rating = self.rating_system().initial_rating();
 
             let entry =
                 self.__get_state()
@@ -65,8 +186,14 @@ pub trait AbstractLeaderboard {
                         avatar: performance.avatar,
                         badges: None,
                         previous_rank: None,
-                        elo: 1200.0,
+                        elo: initial_rating.rating,
+                        deviation: initial_rating.deviation,
+                        volatility: initial_rating.volatility,
+                        rated_streams: 0,
+                        missed_streams: 0,
                         score: 0.0,
+                        participated: false,
+                        contributions: None,
                     });
 
             let badges: Vec<BadgeInformation> = performance
@@ -76,107 +203,303 @@ pub trait AbstractLeaderboard {
                 .unwrap_or_default();
 
             entry.score = score;
+            entry.participated = true;
             entry.badges = Some(badges);
+            entry.contributions = contributions;
         }
     }
 
-    fn save(&mut self) {
+    /// Rates the users and saves the leaderboard, with a header describing
+    /// the run. If the run has a stream, a snapshot is also saved to the
+    /// leaderboard's history.
+    fn save(&mut self, run: &RunInfo) {
         info!("Saving {} leaderboard...", self.get_name());
-        self.__calculate_new_elo();
-        let to_save: Vec<LeaderboardExportItem> = self
+        /* Users with a broken rating are taken out before anyone is rated,
+        so they can't be sampled as opponents */
+        let vod_id = run.stream.as_ref().map(|stream| stream.id.as_str());
+        let quarantined: Vec<QuarantinedUser> = self
+            .__get_state()
+            .values()
+            .filter(|state| !state.rating().is_finite())
+            .map(|state| QuarantinedUser::new(state, vod_id))
+            .collect();
+        for user in quarantined.iter() {
+            self.__get_state().remove(&user.id);
+        }
+        if let Err(e) = quarantine(self.get_store().as_ref(), &self.get_name(), &quarantined) {
+            warn!("Could not save the {} quarantine: {}", self.get_name(), e);
+        }
+
+        let rating_system = self.rating_system();
+        let opponents = self.__sample_opponents();
+        let explanations = self
+            .explain()
+            .then(|| self.__explain_users(rating_system.as_ref(), &opponents));
+        self.__update_ratings(rating_system.as_ref(), &opponents);
+
+        let provisional_streams = self.provisional_streams();
+        let inactive_after = self.inactive_after();
+        let mut sorted_to_save: Vec<(LeaderboardExportItem, f32)> = self
             .__get_state()
             .values()
-            .map(|inner_state| LeaderboardExportItem {
-                id: inner_state.id.clone(),
-                rank: 0,
-                elo: inner_state.elo,
-                username: inner_state.username.clone(),
-                delta: 0,
-                avatar: inner_state.avatar.clone(),
-                badges: inner_state.badges.clone().unwrap_or_default(),
+            .map(|inner_state| {
+                let item = LeaderboardExportItem {
+                    id: inner_state.id.clone(),
+                    rank: 0,
+                    elo: inner_state.elo,
+                    deviation: inner_state.deviation,
+                    volatility: inner_state.volatility,
+                    rated_streams: Some(inner_state.rated_streams),
+                    missed_streams: inner_state.missed_streams,
+                    provisional: inner_state.rated_streams < provisional_streams,
+                    inactive: inactive_after
+                        .is_some_and(|inactive_after| inner_state.missed_streams >= inactive_after),
+                    username: inner_state.username.clone(),
+                    delta: 0,
+                    avatar: inner_state.avatar.clone(),
+                    badges: inner_state.badges.clone().unwrap_or_default(),
+                };
+                (item, inner_state.score)
             })
             .collect();
 
-        // Update rank and delta
-        let mut sorted_to_save = to_save.clone();
-        sorted_to_save.sort_by(|a, b| b.elo.partial_cmp(&a.elo).unwrap());
-        if sorted_to_save.is_empty() {
-            warn!("Nothing to save for leaderboard {}", self.get_name())
-        }
+        // Highest elo first. Users with the same elo are listed by score, then id
+        sorted_to_save.sort_by(|(a, a_score), (b, b_score)| {
+            b.elo
+                .total_cmp(&a.elo)
+                .then_with(|| b_score.total_cmp(a_score))
+                .then_with(|| a.id.cmp(&b.id))
+        });
         if sorted_to_save.is_empty() {
             warn!("Nothing to save for leaderboard {}", self.get_name())
         }
 
-        let updated_to_save: Vec<LeaderboardExportItem> = sorted_to_save
-            .into_iter()
-            .enumerate()
-            .map(|(i, mut item)| {
-                item.rank = (i + 1) as u32;
-                if let Some(state) = self.__get_state().get(&item.id) {
-                    if let Some(previous_rank) = state.previous_rank {
-                        item.delta = previous_rank as i64 - item.rank as i64;
-                    }
+        // Update rank and delta. Users with the same elo share the rank of
+        // the first of them, and the next rank is skipped ("1224" ranking)
+        let mut updated_to_save: Vec<LeaderboardExportItem> =
+            Vec::with_capacity(sorted_to_save.len());
+        for (i, (mut item, _)) in sorted_to_save.into_iter().enumerate() {
+            item.rank = match updated_to_save.last() {
+                Some(previous) if previous.elo == item.elo => previous.rank,
+                _ => (i + 1) as u32,
+            };
+            if let Some(state) = self.__get_state().get(&item.id) {
+                if let Some(previous_rank) = state.previous_rank {
+                    item.delta = previous_rank as i64 - item.rank as i64;
                 }
-                item
-            })
+            }
+            updated_to_save.push(item);
+        }
+
+        if let Some(mut explanations) = explanations {
+            let users = updated_to_save
+                .iter()
+                .filter_map(|item| {
+                    let mut user = explanations.remove(&item.id)?;
+                    user.elo_after = item.elo;
+                    user.elo_delta = item.elo - user.elo_before;
+                    user.deviation_after = item.deviation;
+                    user.rank = item.rank;
+                    Some(user)
+                })
+                .collect();
+            let explanation = LeaderboardExplanation {
+                leaderboard: self.get_name(),
+                rating_system: rating_system.get_name(),
+                k: self.get_k_factor(),
+                opponents: opponents
+                    .iter()
+                    .map(|(score, rating)| SampledOpponent {
+                        score,
+                        elo: rating.rating,
+                        deviation: rating.deviation,
+                    })
+                    .collect(),
+                users,
+            };
+            if let Err(e) = explanation.save(self.get_store().as_ref()) {
+                warn!("Could not save the {} explanation: {}", self.get_name(), e);
+            }
+        }
+
+        let participants: HashSet<String> = self
+            .__get_state()
+            .values()
+            .filter(|state| state.participated)
+            .map(|state| state.id.clone())
             .collect();
+        if let Some(stream) = run.stream.as_ref() {
+            let snapshot =
+                LeaderboardSnapshot::new(stream, &updated_to_save, |id| participants.contains(id));
+            if let Err(e) = snapshot.save(
+                self.get_store().as_ref(),
+                &self.get_name(),
+                self.history_streams(),
+            ) {
+                warn!("Could not save the {} snapshot: {}", self.get_name(), e);
+            }
+        }
 
-        let msg = LeaderboardExport::from(updated_to_save);
-        let path = format!("{}.bin", self.get_name());
+        let msg = LeaderboardExport {
+            items: updated_to_save,
+            header: Some(LeaderboardExportHeader::new(run, participants.len() as u32)),
+        };
         let buf = msg.encode_to_vec();
 
-        fs::File::create(path).unwrap().write_all(&buf).unwrap();
+        if let Err(e) = write_checked(
+            self.get_store().as_ref(),
+            &self.get_key(),
+            &buf,
+            self.backups(),
+        ) {
+            error!("Could not save the {} leaderboard: {}", self.get_name(), e);
+            return;
+        }
+
+        if let Err(e) = write_exports(
+            self.get_store().as_ref(),
+            &self.get_name(),
+            &msg,
+            &self.export_formats(),
+            self.page_size(),
+        ) {
+            warn!(
+                "Could not export the {} leaderboard: {}",
+                self.get_name(),
+                e
+            );
+        }
 
         info!("{} leaderboard saved", self.get_name());
     }
 
     fn __calculate_new_elo(&mut self) {
+        let rating_system = self.rating_system();
+        let opponents = self.__sample_opponents();
+        self.__update_ratings(rating_system.as_ref(), &opponents);
+    }
+
+    /// Picks the (score, rating) pairs every user is compared against,
+    /// from the users who chatted
+    fn __sample_opponents(&mut self) -> Opponents {
         let all_scores: Vec<f32> = self
             .__get_state()
             .values()
+            .filter(|state| state.participated)
             .map(|state| state.score)
             .collect();
         let sample_scores = self.percentiles(&all_scores, 0.0, 100.0, 0.1);
-        // Build a vector of sample users, where the first element is the score and the second element is the elo
-        // The elo is the elo of the user in state with the closest score
-        let sample_users: Vec<(f32, f32)> = sample_scores
-            .iter()
-            .map(|score| {
-                let closest_user = self
-                    .__get_state()
-                    .values()
-                    .min_by(|a, b| {
-                        (a.score - score)
-                            .abs()
-                            .partial_cmp(&(b.score - score).abs())
-                            .unwrap()
-                    })
-                    .unwrap();
-                (*score, closest_user.elo)
-            })
+        // Build a vector of sample users, where the first element is the score and the second element is the rating
+        // The rating is the rating of the user in state with the closest score
+        let mut participants: Vec<&LeaderboardInnerState> = self
+            .__get_state()
+            .values()
+            .filter(|state| state.participated)
             .collect();
+        participants.sort_by(|a, b| {
+            a.score
+                .total_cmp(&b.score)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        Opponents::new(
+            sample_scores
+                .iter()
+                .map(|score| (*score, closest_user(&participants, *score).rating()))
+                .collect(),
+        )
+    }
 
-        // Calculate the new elo for each user
+    /// Rates each user who chatted from how they did against the
+    /// opponents, and the others by the absence policy
+    fn __update_ratings(&mut self, rating_system: &dyn RatingSystem, opponents: &Opponents) {
+        let name = self.get_name();
+        let absence_policy = self.absence_policy();
+        let initial_elo = rating_system.initial_rating().rating;
         self.__get_state().values_mut().for_each(|state| {
-            let diff: f32 = sample_users
-                .iter()
-                .map(|(sample_score, sample_elo)| {
-                    let won = state.score > *sample_score;
-                    let p = 1.0 / (1.0 + 10.0_f32.powf((sample_elo - state.elo) / 400.0));
-                    K * (won as u8 as f32 - p)
-                })
-                .sum();
-            state.elo += diff;
+            if state.participated {
+                state.rated_streams += 1;
+                state.missed_streams = 0;
+            } else {
+                state.missed_streams += 1;
+                match absence_policy {
+                    AbsencePolicy::Freeze => {
+                        set_finite_rating(state, rating_system.idle(state.rating()), &name);
+                        return;
+                    }
+                    AbsencePolicy::Decay { rate, after } => {
+                        let mut rating = rating_system.idle(state.rating());
+                        if state.missed_streams > after {
+                            rating.rating -= rate * (rating.rating - initial_elo);
+                        }
+                        set_finite_rating(state, rating, &name);
+                        return;
+                    }
+                    AbsencePolicy::LostToEveryone => {}
+                }
+            }
+
+            let wins = if state.participated {
+                opponents.wins(state.score)
+            } else {
+                0
+            };
+            let rating = rating_system.rate_against(state.rating(), opponents, wins);
+            set_finite_rating(state, rating, &name);
         });
     }
 
+    /// Explains how each user does against the opponents, before their
+    /// elo is updated
+    fn __explain_users(
+        &mut self,
+        rating_system: &dyn RatingSystem,
+        opponents: &Opponents,
+    ) -> HashMap<String, UserExplanation> {
+        let rated_if_absent = self.absence_policy() == AbsencePolicy::LostToEveryone;
+        let no_opponents = Opponents::default();
+        self.__get_state()
+            .values()
+            .map(|state| {
+                let opponents = if state.participated || rated_if_absent {
+                    opponents
+                } else {
+                    &no_opponents
+                };
+                let wins = if state.participated {
+                    opponents.wins(state.score) as u32
+                } else {
+                    0
+                };
+                let expected_wins = rating_system.expected_wins(&state.rating(), opponents);
+                let explanation = UserExplanation {
+                    id: state.id.clone(),
+                    username: state.username.clone(),
+                    score: state.score,
+                    participated: state.participated,
+                    contributions: state.contributions.clone().unwrap_or_default(),
+                    wins,
+                    losses: opponents.len() as u32 - wins,
+                    expected_wins,
+                    elo_before: state.elo,
+                    elo_after: state.elo,
+                    elo_delta: 0.0,
+                    deviation_before: state.deviation,
+                    deviation_after: state.deviation,
+                    rank: 0,
+                    previous_rank: state.previous_rank,
+                };
+                (state.id.clone(), explanation)
+            })
+            .collect()
+    }
+
     fn percentiles(&self, scores: &[f32], start: f32, end: f32, step: f32) -> Vec<f32> {
         if scores.is_empty() {
             return Vec::new();
         }
 
         let mut sorted_scores = scores.to_vec();
-        sorted_scores.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        sorted_scores.sort_by(|a, b| a.total_cmp(b));
         let step_count = ((end - start) / step) as usize + 1;
         let chunk_size = (sorted_scores.len() as f32 / step_count as f32).ceil() as usize;
         let chunks = sorted_scores.chunks(chunk_size);
@@ -185,6 +508,43 @@ pub trait AbstractLeaderboard {
     }
 }
 
+/// Gives a user their new rating, unless it isn't a finite number, in
+/// which case they keep their previous one
+fn set_finite_rating(state: &mut LeaderboardInnerState, rating: Rating, leaderboard: &str) {
+    if rating.is_finite() {
+        state.set_rating(rating);
+    } else {
+        warn!(
+            "{} leaderboard: {} ({}) was rated {:?}, keeping their previous rating",
+            leaderboard, state.username, state.id, rating
+        );
+    }
+}
+
+/// The user with the score closest to `score`, picking the lowest id
+/// among users equally close. `users` must be sorted by score, then id.
+fn closest_user<'a>(users: &[&'a LeaderboardInnerState], score: f32) -> &'a LeaderboardInnerState {
+    /* The closest users are the first of those with the highest score not
+    above `score`, and the first of those with the lowest score above it */
+    let above = users.partition_point(|user| user.score <= score);
+    let below = above.checked_sub(1).map(|last| {
+        let below_score = users[last].score;
+        users.partition_point(|user| user.score < below_score)
+    });
+    [below, (above < users.len()).then_some(above)]
+        .into_iter()
+        .flatten()
+        .map(|index| users[index])
+        .min_by(|a, b| {
+            (a.score - score)
+                .abs()
+                .total_cmp(&(b.score - score).abs())
+                // Users equally close are picked by id, not by map order
+                .then_with(|| a.id.cmp(&b.id))
+        })
+        .unwrap()
+}
+
 #[macro_export]
 macro_rules! is_message_origin {
     ($performance:expr, $tag:pat) => {
@@ -196,3 +556,290 @@ macro_rules! is_message_origin {
         )
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use twitch_utils::datasource::VodInfo;
+
+    use super::*;
+    use crate::_types::leaderboardtypes::LeaderboardHistory;
+    use crate::leaderboards::configured::ConfiguredLeaderboard;
+    use crate::leaderboards::definition::LeaderboardsConfig;
+    use crate::leaderboards::store::memory::MemoryStore;
+
+    fn leaderboard(extra: &str, store: Arc<dyn LeaderboardStore>) -> ConfiguredLeaderboard {
+        let config: LeaderboardsConfig = format!(
+            "[[leaderboard]]\nname = \"test\"\nscore = \"all\"\n{}",
+            extra
+        )
+        .parse()
+        .unwrap();
+        ConfiguredLeaderboard::new(config.leaderboards[0].clone(), store)
+    }
+
+    fn item(id: &str, elo: f32, rated_streams: Option<u32>) -> LeaderboardExportItem {
+        LeaderboardExportItem {
+            id: id.to_string(),
+            elo,
+            rated_streams,
+            ..Default::default()
+        }
+    }
+
+    fn state(id: &str, score: f32) -> LeaderboardInnerState {
+        LeaderboardInnerState {
+            id: id.to_string(),
+            username: id.to_string(),
+            avatar: String::new(),
+            badges: None,
+            previous_rank: None,
+            elo: INITIAL_ELO,
+            deviation: None,
+            volatility: None,
+            rated_streams: 0,
+            missed_streams: 0,
+            score,
+            participated: true,
+            contributions: None,
+        }
+    }
+
+    fn performance(id: &str, score: f32) -> UserChatPerformance {
+        UserChatPerformance {
+            id: id.to_string(),
+            username: id.to_string(),
+            avatar: String::new(),
+            metrics: HashMap::from([("text".to_string(), score)]),
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn stream(id: &str, start: i64) -> VodInfo {
+        let start = DateTime::from_timestamp(start, 0).unwrap().fixed_offset();
+        VodInfo {
+            id: id.to_string(),
+            start,
+            end: start,
+        }
+    }
+
+    fn saved(store: &dyn LeaderboardStore) -> Vec<LeaderboardExportItem> {
+        let data = store.read("test.bin").unwrap().unwrap();
+        LeaderboardExport::decode(&*data).unwrap().items
+    }
+
+    /// Elo and missed streams of a user who was on the leaderboard with an
+    /// elo of 1400, after each of the three streams they then sit out
+    fn absent_user(extra: &str) -> Vec<(f32, u32)> {
+        let store = Arc::new(MemoryStore::new());
+        let initial = LeaderboardExport::from(vec![item("absent", 1400.0, Some(5))]);
+        store.write("test.bin", &initial.encode_to_vec()).unwrap();
+
+        (0..3)
+            .map(|_| {
+                let mut leaderboard = leaderboard(extra, store.clone());
+                leaderboard.update_leaderboard(performance("a", 3.0));
+                leaderboard.update_leaderboard(performance("b", 1.0));
+                leaderboard.save(&RunInfo::default());
+                let absent = saved(store.as_ref())
+                    .into_iter()
+                    .find(|item| item.id == "absent")
+                    .unwrap();
+                (absent.elo, absent.missed_streams)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frozen_users_keep_their_elo_while_absent() {
+        assert_eq!(absent_user(""), [(1400.0, 1), (1400.0, 2), (1400.0, 3)]);
+    }
+
+    #[test]
+    fn decaying_users_move_back_to_the_initial_elo_after_a_grace_period() {
+        assert_eq!(
+            absent_user("absence = { policy = \"decay\", rate = 0.5, after = 1 }"),
+            [(1400.0, 1), (1300.0, 2), (1250.0, 3)]
+        );
+    }
+
+    #[test]
+    fn users_who_lose_to_everyone_drop_every_stream_they_miss() {
+        let streams = absent_user("absence = { policy = \"lost_to_everyone\" }");
+
+        assert!(streams[0].0 < 1400.0);
+        assert!(streams[1].0 < streams[0].0);
+        assert!(streams[2].0 < streams[1].0);
+        assert_eq!(
+            streams
+                .iter()
+                .map(|(_, missed)| *missed)
+                .collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn users_saved_before_rated_streams_are_not_provisional() {
+        let store = Arc::new(MemoryStore::new());
+        let legacy = LeaderboardExport::from(vec![
+            item("veteran", 1300.0, None),
+            item("newcomer", 1200.0, Some(1)),
+        ]);
+        store.write("test.bin", &legacy.encode_to_vec()).unwrap();
+
+        leaderboard("provisional_streams = 3", store.clone()).save(&RunInfo::default());
+
+        let items = saved(store.as_ref());
+        assert_eq!(items[0].id, "veteran");
+        assert_eq!(items[0].rated_streams, Some(3));
+        assert!(!items[0].provisional);
+        assert_eq!(items[1].rated_streams, Some(1));
+        assert!(items[1].provisional);
+    }
+
+    #[test]
+    fn a_leaderboard_none_of_which_can_be_read_starts_over() {
+        let store = Arc::new(MemoryStore::new());
+        store.write("test.bin", b"not a leaderboard").unwrap();
+
+        leaderboard("", store.clone()).save(&RunInfo::default());
+
+        assert!(saved(store.as_ref()).is_empty());
+        assert_eq!(
+            store.read(&broken_key("test.bin")).unwrap().unwrap(),
+            b"not a leaderboard"
+        );
+    }
+
+    #[test]
+    fn the_closest_user_is_picked_by_lowest_id_on_ties() {
+        let states = [
+            state("d", 1.0),
+            state("b", 1.0),
+            state("c", 3.0),
+            state("a", 3.0),
+            state("e", 7.0),
+        ];
+        let mut users: Vec<&LeaderboardInnerState> = states.iter().collect();
+        users.sort_by(|a, b| {
+            a.score
+                .total_cmp(&b.score)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        let closest = |score| closest_user(&users, score).id.as_str();
+
+        assert_eq!(closest(1.0), "b");
+        assert_eq!(closest(0.0), "b");
+        /* Equally close to 1 and 3 */
+        assert_eq!(closest(2.0), "a");
+        assert_eq!(closest(2.9), "a");
+        assert_eq!(closest(5.0), "a");
+        assert_eq!(closest(5.1), "e");
+        assert_eq!(closest(100.0), "e");
+    }
+
+    #[test]
+    fn the_closest_user_matches_scanning_every_user() {
+        let states: Vec<LeaderboardInnerState> = (0..200)
+            .map(|i| {
+                state(
+                    &format!("{:03}", (i * 37) % 200),
+                    ((i * 13) % 50) as f32 * 0.5,
+                )
+            })
+            .collect();
+        let mut users: Vec<&LeaderboardInnerState> = states.iter().collect();
+        users.sort_by(|a, b| {
+            a.score
+                .total_cmp(&b.score)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+
+        for step in -10..300 {
+            let score = step as f32 * 0.1;
+            let scanned = states
+                .iter()
+                .min_by(|a, b| {
+                    (a.score - score)
+                        .abs()
+                        .total_cmp(&(b.score - score).abs())
+                        .then_with(|| a.id.cmp(&b.id))
+                })
+                .unwrap();
+            assert_eq!(
+                closest_user(&users, score).id,
+                scanned.id,
+                "score {}",
+                score
+            );
+        }
+    }
+
+    #[test]
+    fn tied_users_share_a_rank_and_broken_ratings_are_quarantined() {
+        let store = Arc::new(MemoryStore::new());
+        let mut items = vec![
+            item("d", 1200.0, Some(1)),
+            item("c", 1250.0, Some(1)),
+            item("b", 1250.0, Some(1)),
+            item("a", 1300.0, Some(1)),
+            item("broken", f32::NAN, Some(1)),
+        ];
+        for (rank, item) in items.iter_mut().enumerate() {
+            item.rank = rank as u32 + 1;
+        }
+        store
+            .write("test.bin", &LeaderboardExport::from(items).encode_to_vec())
+            .unwrap();
+
+        leaderboard("", store.clone()).save(&RunInfo::default());
+
+        let ranks: Vec<(String, u32, i64)> = saved(store.as_ref())
+            .into_iter()
+            .map(|item| (item.id, item.rank, item.delta))
+            .collect();
+        assert_eq!(
+            ranks,
+            [
+                ("a".to_string(), 1, 3),
+                ("b".to_string(), 2, 1),
+                ("c".to_string(), 2, 0),
+                ("d".to_string(), 4, -3),
+            ]
+        );
+        let quarantined = store.read("test.quarantine.json").unwrap().unwrap();
+        assert!(String::from_utf8(quarantined).unwrap().contains("broken"));
+    }
+
+    #[test]
+    fn users_who_sit_a_stream_out_are_still_in_its_history() {
+        let store = Arc::new(MemoryStore::new());
+        let streams = [
+            vec![("a", 3.0), ("b", 2.0), ("c", 1.0)],
+            vec![("c", 5.0), ("d", 1.0)],
+        ];
+        for (i, performances) in streams.into_iter().enumerate() {
+            let mut leaderboard = leaderboard("", store.clone());
+            for (id, score) in performances {
+                leaderboard.update_leaderboard(performance(id, score));
+            }
+            leaderboard.save(&RunInfo::new(stream(&i.to_string(), i as i64 * 100)));
+        }
+
+        let history = LeaderboardHistory::load(store.as_ref(), "test").unwrap();
+        let a = history.user_series("a");
+        assert_eq!(a.len(), 2);
+        assert!(a[0].participated);
+        assert!(!a[1].participated);
+        assert_eq!(a[0].elo, a[1].elo);
+        /* Ranks match the leaderboard after each stream, including for
+        users who didn't chat */
+        for item in saved(store.as_ref()) {
+            let series = history.user_series(&item.id);
+            assert_eq!(series.last().unwrap().rank, item.rank, "{}", item.id);
+        }
+        assert_eq!(history.user_series("d").len(), 1);
+    }
+}
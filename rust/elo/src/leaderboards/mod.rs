@@ -1,105 +1,212 @@
-mod bitsonly;
-mod chatonly;
-mod copypastaleaders;
-mod discordlivestreamchat;
-mod leaderboardtrait;
-mod nonvips;
-mod overall;
-mod partnersonly;
-mod subsonly;
-mod topemote;
-
-use futures::join;
-
-use log::error;
-use tokio::sync::broadcast;
+pub mod configured;
+pub mod definition;
+pub mod explain;
+pub mod export;
+pub mod header;
+pub mod history;
+pub mod leaderboardtrait;
+pub mod quarantine;
+pub mod rating;
+pub mod season;
+pub mod store;
+
+use std::sync::Arc;
+
+use futures::future::join_all;
+
+use log::{error, info};
+use tokio::sync::mpsc;
+use twitch_utils::datasource::VodInfo;
 
 use crate::{
-    _types::clptypes::UserChatPerformance, leaderboards::leaderboardtrait::AbstractLeaderboard,
+    _types::clptypes::UserChatPerformance,
+    fanout::{self, FanOut, FanOutError},
+    leaderboards::{
+        configured::ConfiguredLeaderboard,
+        definition::LeaderboardsConfig,
+        export::pages_directory,
+        header::RunInfo,
+        history::snapshot_directory,
+        leaderboardtrait::AbstractLeaderboard,
+        store::{filesystem::FileStore, storetrait::LeaderboardStore},
+    },
 };
 
-async fn calc_leaderboard<M: AbstractLeaderboard + Sync + Send + 'static>(
-    leaderboard: &mut M,
-    mut reciever: broadcast::Receiver<UserChatPerformance>,
+async fn calc_leaderboard(
+    leaderboard: &mut Box<dyn AbstractLeaderboard + Send>,
+    mut reciever: mpsc::Receiver<UserChatPerformance>,
+    run: &RunInfo,
 ) {
     /*
-    Update the leaderboard based on chat messages sent by a tokio broadcast channel
+    Update the leaderboard based on user performances sent by the fan-out
     */
-    loop {
-        let user_chat_performance: UserChatPerformance = match reciever.recv().await {
-            Ok(user_chat_performance) => user_chat_performance,
-            Err(_) => break,
-        };
+    while let Some(user_chat_performance) = reciever.recv().await {
         leaderboard.update_leaderboard(user_chat_performance);
     }
-    leaderboard.save();
+    leaderboard.save(run);
 }
 
 pub struct LeaderboardProcessor {
-    bitsonly: bitsonly::BitsOnly,
-    chatonly: chatonly::ChatOnly,
-    copypasta: copypastaleaders::CopypastaLeaders,
-    nonvips: nonvips::NonVIPS,
-    overall: overall::Overall,
-    subsonly: subsonly::SubsOnly,
-    topemote: topemote::TopEmote,
-    discordlivestreamchat: discordlivestreamchat::DiscordLivestreamChat,
-    partnersonly: partnersonly::PartnersOnly,
+    leaderboards: Vec<Box<dyn AbstractLeaderboard + Send>>,
+    run: RunInfo,
+}
+
+impl Default for LeaderboardProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LeaderboardProcessor {
+    /// Creates the leaderboards that ship with this crate
     pub fn new() -> Self {
-        let bitsonly = bitsonly::BitsOnly::new();
-        let chatonly = chatonly::ChatOnly::new();
-        let copypasta = copypastaleaders::CopypastaLeaders::new();
-        let nonvips = nonvips::NonVIPS::new();
-        let overall = overall::Overall::new();
-        let subsonly = subsonly::SubsOnly::new();
-        let topemote = topemote::TopEmote::new();
-        let discordlivestreamchat = discordlivestreamchat::DiscordLivestreamChat::new();
-        let partnersonly = partnersonly::PartnersOnly::new();
+        Self::from_config(LeaderboardsConfig::default())
+    }
+
+    /// Creates the leaderboards defined in a TOML file
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        info!("Loading leaderboard definitions from {}", path);
+        Ok(Self::from_config(LeaderboardsConfig::from_file(path)?))
+    }
+
+    /// Creates the leaderboards of a config, kept in the working directory
+    pub fn from_config(config: LeaderboardsConfig) -> Self {
+        Self::from_config_with_store(config, Arc::new(FileStore::default()))
+    }
+
+    /// Creates the leaderboards of a config, kept in a store
+    pub fn from_config_with_store(
+        config: LeaderboardsConfig,
+        store: Arc<dyn LeaderboardStore>,
+    ) -> Self {
+        let leaderboards = config
+            .leaderboards
+            .into_iter()
+            .map(|definition| {
+                Box::new(ConfiguredLeaderboard::new(definition, store.clone()))
+                    as Box<dyn AbstractLeaderboard + Send>
+            })
+            .collect();
 
         Self {
-            bitsonly,
-            chatonly,
-            copypasta,
-            nonvips,
-            overall,
-            subsonly,
-            topemote,
-            discordlivestreamchat,
-            partnersonly,
+            leaderboards,
+            run: RunInfo::default(),
         }
     }
 
+    /// Sets the stream the performances are from, so every leaderboard
+    /// also saves a snapshot to its history
+    pub fn with_stream(mut self, stream: VodInfo) -> Self {
+        self.run.stream = Some(stream);
+        self
+    }
+
+    /// Sets what the performances were generated from, which is saved in
+    /// the header of every leaderboard
+    pub fn with_run(mut self, run: RunInfo) -> Self {
+        self.run = run;
+        self
+    }
+
+    /// Adds a leaderboard, replacing any leaderboard with the same name
+    pub fn register(&mut self, leaderboard: Box<dyn AbstractLeaderboard + Send>) {
+        let name = leaderboard.get_name();
+        self.leaderboards
+            .retain(|existing| existing.get_name() != name);
+        self.leaderboards.push(leaderboard);
+    }
+
+    /// Names of all the leaderboards
+    pub fn names(&self) -> Vec<String> {
+        self.leaderboards.iter().map(|l| l.get_name()).collect()
+    }
+
     pub async fn run(&mut self, performances: Vec<UserChatPerformance>) {
-        let (broadcast_sender, broadcast_reciever) = broadcast::channel(100000);
-
-        join!(
-            send_performances(broadcast_sender, performances),
-            calc_leaderboard(&mut self.bitsonly, broadcast_reciever.resubscribe()),
-            calc_leaderboard(&mut self.chatonly, broadcast_reciever.resubscribe()),
-            calc_leaderboard(&mut self.copypasta, broadcast_reciever.resubscribe()),
-            calc_leaderboard(&mut self.nonvips, broadcast_reciever.resubscribe()),
-            calc_leaderboard(&mut self.overall, broadcast_reciever.resubscribe()),
-            calc_leaderboard(&mut self.subsonly, broadcast_reciever.resubscribe()),
-            calc_leaderboard(&mut self.topemote, broadcast_reciever.resubscribe()),
-            calc_leaderboard(
-                &mut self.discordlivestreamchat,
-                broadcast_reciever.resubscribe()
-            ),
-            calc_leaderboard(&mut self.partnersonly, broadcast_reciever.resubscribe())
-        );
+        let mut fan_out = FanOut::new(fanout::DEFAULT_CAPACITY);
+
+        let calculations = join_all(self.leaderboards.iter_mut().map(|leaderboard| {
+            let receiver = fan_out.subscribe(leaderboard.get_name());
+            calc_leaderboard(leaderboard, receiver, &self.run)
+        }));
+
+        let (sent, _) = futures::join!(send_performances(fan_out, performances), calculations);
+        if let Err(e) = sent {
+            panic!("Leaderboards are incomplete: {}", e);
+        }
+    }
+}
+
+/// Keys of every file a leaderboard saved: the leaderboard and the files
+/// next to it, its backups, its history and its pages
+pub fn leaderboard_files(
+    store: &dyn LeaderboardStore,
+    name: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let prefix = format!("{}.", name);
+    let mut keys = Vec::new();
+    for directory in ["", "backups"] {
+        keys.extend(store.list(directory)?.into_iter().filter(|key| {
+            key.rsplit('/')
+                .next()
+                .is_some_and(|file| file.starts_with(&prefix))
+        }));
     }
+    keys.extend(store.list(&snapshot_directory(name))?);
+    keys.extend(store.list(&pages_directory(name))?);
+    Ok(keys)
 }
 
+/// Sends the performances to every leaderboard, then closes the fan-out
 pub async fn send_performances(
-    sender: broadcast::Sender<UserChatPerformance>,
+    fan_out: FanOut<UserChatPerformance>,
     performances: Vec<UserChatPerformance>,
-) {
+) -> Result<(), FanOutError> {
     for performance in performances {
-        if let Err(e) = sender.send(performance) {
+        if let Err(e) = fan_out.send(performance).await {
             error!("Error sending performance to leaderboards: {}", e);
+            return Err(e);
         }
     }
+    fan_out.close();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::leaderboards::store::memory::MemoryStore;
+
+    #[test]
+    fn leaderboard_files_leave_other_leaderboards_alone() {
+        let store = MemoryStore::new();
+        for key in [
+            "chat.bin",
+            "chat.bin.sha256",
+            "chat.history.bin",
+            "chat-only.bin",
+            "backups/chat.bin.1",
+            "backups/chat-only.bin.1",
+            "history/chat/1-1.bin",
+            "pages/chat/1.bin",
+            "pages/chat-only/1.bin",
+            "final/chat.bin",
+        ] {
+            store.write(key, b"").unwrap();
+        }
+
+        let mut files = leaderboard_files(&store, "chat").unwrap();
+        files.sort();
+
+        assert_eq!(
+            files,
+            [
+                "backups/chat.bin.1",
+                "chat.bin",
+                "chat.bin.sha256",
+                "chat.history.bin",
+                "history/chat/1-1.bin",
+                "pages/chat/1.bin",
+            ]
+        );
+    }
 }
@@ -0,0 +1,67 @@
+//! Users whose rating isn't a number, or is infinite. They are left out
+//! of the leaderboard and added to `{name}.quarantine.json`, so that
+//! one broken rating can't break the ranks of everyone else.
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::_types::leaderboardtypes::LeaderboardInnerState;
+use crate::leaderboards::store::storetrait::LeaderboardStore;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedUser {
+    pub id: String,
+    pub username: String,
+    /// The rating as text, since JSON has no NaN or infinity
+    pub elo: String,
+    pub deviation: Option<String>,
+    pub volatility: Option<String>,
+    pub rated_streams: u32,
+    /// The stream being rated when the user was quarantined
+    pub vod_id: Option<String>,
+}
+
+impl QuarantinedUser {
+    pub fn new(state: &LeaderboardInnerState, vod_id: Option<&str>) -> Self {
+        Self {
+            id: state.id.clone(),
+            username: state.username.clone(),
+            elo: state.elo.to_string(),
+            deviation: state.deviation.map(|deviation| deviation.to_string()),
+            volatility: state.volatility.map(|volatility| volatility.to_string()),
+            rated_streams: state.rated_streams,
+            vod_id: vod_id.map(str::to_string),
+        }
+    }
+}
+
+pub fn quarantine_key(name: &str) -> String {
+    format!("{}.quarantine.json", name)
+}
+
+/// Adds users to the leaderboard's quarantine, keeping the ones already
+/// in it
+pub fn quarantine(
+    store: &dyn LeaderboardStore,
+    name: &str,
+    users: &[QuarantinedUser],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if users.is_empty() {
+        return Ok(());
+    }
+    for user in users {
+        error!(
+            "{} leaderboard: quarantining {} ({}) with an elo of {}",
+            name, user.username, user.id, user.elo
+        );
+    }
+
+    let key = quarantine_key(name);
+    let mut quarantined: Vec<QuarantinedUser> = match store.read(&key)? {
+        Some(data) => serde_json::from_slice(&data)?,
+        None => Vec::new(),
+    };
+    quarantined.extend_from_slice(users);
+    store.write(&key, serde_json::to_string_pretty(&quarantined)?.as_bytes())?;
+    info!("{} quarantined users saved to {}", name, key);
+    Ok(())
+}
@@ -0,0 +1,105 @@
+//! The Elo rating system
+use crate::leaderboards::leaderboardtrait::INITIAL_ELO;
+use crate::leaderboards::rating::ratingtrait::{Game, Opponents, Rating, RatingSystem};
+
+/// Elo, updating a user's rating by `k` times their actual minus their
+/// expected outcome in every game
+#[derive(Debug, Clone)]
+pub struct Elo {
+    pub k: f32,
+}
+
+/// Chance of a user beating an opponent, going by their elos
+pub fn expected_outcome(elo: f32, opponent_elo: f32) -> f32 {
+    1.0 / (1.0 + 10.0_f32.powf((opponent_elo - elo) / 400.0))
+}
+
+impl RatingSystem for Elo {
+    fn get_name(&self) -> String {
+        "elo".to_string()
+    }
+
+    fn initial_rating(&self) -> Rating {
+        Rating {
+            rating: INITIAL_ELO,
+            deviation: None,
+            volatility: None,
+        }
+    }
+
+    fn expected_outcome(&self, rating: &Rating, opponent: &Rating) -> f32 {
+        expected_outcome(rating.rating, opponent.rating)
+    }
+
+    fn rate(&self, rating: Rating, games: &[Game]) -> Rating {
+        let diff: f32 = games
+            .iter()
+            .map(|game| {
+                let p = expected_outcome(rating.rating, game.opponent.rating);
+                self.k * (game.outcome - p)
+            })
+            .sum();
+        Rating {
+            rating: rating.rating + diff,
+            deviation: None,
+            volatility: None,
+        }
+    }
+
+    fn expected_wins(&self, rating: &Rating, opponents: &Opponents) -> f32 {
+        /* 10^((opponent - elo) / 400) is 10^(opponent / 400) * 10^(-elo / 400),
+        so only one power is needed per user */
+        let scale = 10.0_f64.powf(-rating.rating as f64 / 400.0);
+        opponents
+            .elo_powers()
+            .iter()
+            .map(|power| 1.0 / (1.0 + power * scale))
+            .sum::<f64>() as f32
+    }
+
+    fn rate_against(&self, rating: Rating, opponents: &Opponents, wins: usize) -> Rating {
+        let expected_wins = self.expected_wins(&rating, opponents);
+        Rating {
+            rating: rating.rating + self.k * (wins as f32 - expected_wins),
+            deviation: None,
+            volatility: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(elo: f32) -> Rating {
+        Rating {
+            rating: elo,
+            deviation: None,
+            volatility: None,
+        }
+    }
+
+    #[test]
+    fn rating_against_opponents_matches_playing_every_game() {
+        let elo = Elo { k: 2.0 };
+        let opponents = Opponents::new(
+            (0..1000)
+                .map(|i| (i as f32, rating(900.0 + (i * 7 % 600) as f32)))
+                .collect(),
+        );
+        for user in [800.0, 1200.0, 1450.0, 2000.0] {
+            for wins in [0, 1, 500, 999, 1000] {
+                let played = elo.rate(rating(user), &opponents.games(wins)).rating;
+                let against = elo.rate_against(rating(user), &opponents, wins).rating;
+                assert!(
+                    (played - against).abs() < 0.01,
+                    "{} with {} wins: {} != {}",
+                    user,
+                    wins,
+                    played,
+                    against
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! The Glicko-2 rating system
+//!
+//! See http://www.glicko.net/glicko/glicko2.pdf. Ratings are centred on
+//! the initial elo rather than 1500, so they line up with the other
+//! systems.
+use std::f64::consts::PI;
+
+use serde::Deserialize;
+
+use crate::leaderboards::leaderboardtrait::INITIAL_ELO;
+use crate::leaderboards::rating::ratingtrait::{Game, Rating, RatingSystem};
+
+/// Converts between the Glicko and Glicko-2 scales
+const SCALE: f64 = 173.7178;
+
+/// Convergence tolerance of the volatility iteration
+const EPSILON: f64 = 0.000001;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Glicko2 {
+    /// Deviation of new users, which is also the most a deviation can grow to
+    pub deviation: f32,
+    /// Volatility of new users
+    pub volatility: f32,
+    /// How much the volatility can change between streams
+    pub tau: f32,
+    /// How many games a stream counts as. The games against the sampled
+    /// opponents are weighted to add up to this, so the number of
+    /// opponents doesn't change how much a stream counts.
+    pub games_per_stream: f32,
+}
+
+impl Default for Glicko2 {
+    fn default() -> Self {
+        Self {
+            deviation: 350.0,
+            volatility: 0.06,
+            tau: 0.5,
+            games_per_stream: 10.0,
+        }
+    }
+}
+
+/// A rating on the Glicko-2 scale
+struct Scaled {
+    mu: f64,
+    phi: f64,
+    sigma: f64,
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+fn expected(mu: f64, opponent_mu: f64, opponent_phi: f64) -> f64 {
+    1.0 / (1.0 + (-g(opponent_phi) * (mu - opponent_mu)).exp())
+}
+
+impl Glicko2 {
+    fn scale(&self, rating: &Rating) -> Scaled {
+        Scaled {
+            mu: (rating.rating as f64 - INITIAL_ELO as f64) / SCALE,
+            phi: rating.deviation.unwrap_or(self.deviation) as f64 / SCALE,
+            sigma: rating.volatility.unwrap_or(self.volatility) as f64,
+        }
+    }
+
+    fn unscale(&self, scaled: Scaled) -> Rating {
+        Rating {
+            rating: (INITIAL_ELO as f64 + scaled.mu * SCALE) as f32,
+            deviation: Some(((scaled.phi * SCALE) as f32).min(self.deviation)),
+            volatility: Some(scaled.sigma as f32),
+        }
+    }
+
+    /// Step 5 of the paper, finding the new volatility
+    fn new_volatility(&self, rating: &Scaled, delta: f64, v: f64) -> f64 {
+        let tau = self.tau as f64;
+        let phi_squared = rating.phi * rating.phi;
+        let a = (rating.sigma * rating.sigma).ln();
+        let f = |x: f64| {
+            let ex = x.exp();
+            ex * (delta * delta - phi_squared - v - ex) / (2.0 * (phi_squared + v + ex).powi(2))
+                - (x - a) / (tau * tau)
+        };
+
+        let mut big_a = a;
+        let mut big_b = if delta * delta > phi_squared + v {
+            (delta * delta - phi_squared - v).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * tau) < 0.0 {
+                k += 1.0;
+            }
+            a - k * tau
+        };
+
+        let mut f_a = f(big_a);
+        let mut f_b = f(big_b);
+        let mut iterations = 0;
+        while (big_b - big_a).abs() > EPSILON && iterations < 100 {
+            let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+            let f_c = f(big_c);
+            if f_c * f_b <= 0.0 {
+                big_a = big_b;
+                f_a = f_b;
+            } else {
+                f_a /= 2.0;
+            }
+            big_b = big_c;
+            f_b = f_c;
+            iterations += 1;
+        }
+
+        (big_a / 2.0).exp()
+    }
+}
+
+impl RatingSystem for Glicko2 {
+    fn get_name(&self) -> String {
+        "glicko2".to_string()
+    }
+
+    fn initial_rating(&self) -> Rating {
+        Rating {
+            rating: INITIAL_ELO,
+            deviation: Some(self.deviation),
+            volatility: Some(self.volatility),
+        }
+    }
+
+    fn expected_outcome(&self, rating: &Rating, opponent: &Rating) -> f32 {
+        let rating = self.scale(rating);
+        let opponent = self.scale(opponent);
+        expected(rating.mu, opponent.mu, opponent.phi) as f32
+    }
+
+    fn rate(&self, rating: Rating, games: &[Game]) -> Rating {
+        if games.is_empty() {
+            return self.idle(rating);
+        }
+
+        let weight = if self.games_per_stream > 0.0 {
+            self.games_per_stream as f64 / games.len() as f64
+        } else {
+            1.0
+        };
+        let scaled = self.scale(&rating);
+
+        let mut v_inverse = 0.0;
+        let mut improvement = 0.0;
+        for game in games {
+            let opponent = self.scale(&game.opponent);
+            let g_phi = g(opponent.phi);
+            let e = expected(scaled.mu, opponent.mu, opponent.phi);
+            v_inverse += weight * g_phi * g_phi * e * (1.0 - e);
+            improvement += weight * g_phi * (game.outcome as f64 - e);
+        }
+        if !(v_inverse > 0.0 && v_inverse.is_finite()) {
+            return self.idle(rating);
+        }
+        let v = 1.0 / v_inverse;
+        let delta = v * improvement;
+
+        let sigma = self.new_volatility(&scaled, delta, v);
+        let phi_star = (scaled.phi * scaled.phi + sigma * sigma).sqrt();
+        let phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let mu = scaled.mu + phi * phi * improvement;
+
+        self.unscale(Scaled { mu, phi, sigma })
+    }
+
+    /// The deviation grows while a user isn't playing
+    fn idle(&self, rating: Rating) -> Rating {
+        let scaled = self.scale(&rating);
+        let phi = (scaled.phi * scaled.phi + scaled.sigma * scaled.sigma).sqrt();
+        self.unscale(Scaled { phi, ..scaled })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(elo: f32, deviation: f32) -> Rating {
+        Rating {
+            rating: elo,
+            deviation: Some(deviation),
+            volatility: Some(0.06),
+        }
+    }
+
+    fn game(opponent: Rating, outcome: f32) -> Game {
+        Game { opponent, outcome }
+    }
+
+    #[test]
+    fn matches_the_example_in_the_paper() {
+        /* The paper's example, moved from 1500 to the initial elo, with
+        every game counting once */
+        let glicko2 = Glicko2 {
+            games_per_stream: 0.0,
+            ..Default::default()
+        };
+        let rated = glicko2.rate(
+            rating(1200.0, 200.0),
+            &[
+                game(rating(1100.0, 30.0), 1.0),
+                game(rating(1250.0, 100.0), 0.0),
+                game(rating(1400.0, 300.0), 0.0),
+            ],
+        );
+
+        assert!((rated.rating - 1164.06).abs() < 0.01, "{:?}", rated);
+        assert!(
+            (rated.deviation.unwrap() - 151.52).abs() < 0.01,
+            "{:?}",
+            rated
+        );
+        assert!(
+            (rated.volatility.unwrap() - 0.05999).abs() < 0.00001,
+            "{:?}",
+            rated
+        );
+    }
+
+    #[test]
+    fn equal_users_move_equally_apart() {
+        let glicko2 = Glicko2::default();
+        let user = rating(1200.0, 200.0);
+
+        let winner = glicko2.rate(user, &[game(user, 1.0)]);
+        let loser = glicko2.rate(user, &[game(user, 0.0)]);
+
+        assert!(winner.rating > 1200.0);
+        assert!(loser.rating < 1200.0);
+        assert!((winner.rating - 1200.0 - (1200.0 - loser.rating)).abs() < 0.001);
+        assert_eq!(winner.deviation, loser.deviation);
+        assert_eq!(winner.volatility, loser.volatility);
+    }
+
+    #[test]
+    fn deviation_shrinks_when_rated_and_grows_while_idle() {
+        let glicko2 = Glicko2::default();
+        let user = rating(1200.0, 200.0);
+
+        let rated = glicko2.rate(user, &[game(user, 1.0), game(user, 0.0)]);
+        let idle = glicko2.rate(user, &[]);
+
+        assert!(rated.deviation.unwrap() < 200.0);
+        assert!(idle.deviation.unwrap() > 200.0);
+        assert_eq!(idle.rating, 1200.0);
+
+        /* But never past the deviation of a new user */
+        let mut rating = user;
+        for _ in 0..1000 {
+            rating = glicko2.idle(rating);
+        }
+        assert_eq!(rating.deviation, Some(glicko2.deviation));
+    }
+
+    #[test]
+    fn volatility_stays_finite_after_upsets() {
+        let glicko2 = Glicko2::default();
+        let favourite = rating(3000.0, 30.0);
+        let underdog = rating(-500.0, 30.0);
+
+        for (user, opponent, outcome) in [
+            (underdog, favourite, 1.0),
+            (favourite, underdog, 0.0),
+            (underdog, underdog, 1.0),
+            (rating(1200.0, 350.0), rating(1200.0, 350.0), 0.0),
+        ] {
+            let rated = glicko2.rate(user, &vec![game(opponent, outcome); 1000]);
+            assert!(rated.is_finite(), "{:?}", rated);
+            assert!(rated.volatility.unwrap() > 0.0, "{:?}", rated);
+        }
+    }
+}
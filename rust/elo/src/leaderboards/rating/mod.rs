@@ -0,0 +1,4 @@
+pub mod elo;
+pub mod glicko2;
+pub mod openskill;
+pub mod ratingtrait;
@@ -0,0 +1,207 @@
+//! The OpenSkill rating system, using the Weng-Lin Bradley-Terry model
+//!
+//! See https://jmlr.org/papers/v12/weng11a.html. Each game is a 1v1
+//! between a user and a sampled opponent, and only the user's rating is
+//! updated. Ratings use the elo scale instead of OpenSkill's usual
+//! mu = 25, so they line up with the other systems.
+use serde::Deserialize;
+
+use crate::leaderboards::leaderboardtrait::INITIAL_ELO;
+use crate::leaderboards::rating::ratingtrait::{Game, Rating, RatingSystem};
+
+/// Smallest fraction of the variance a stream can leave
+const KAPPA: f64 = 0.0001;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenSkill {
+    /// Uncertainty (sigma) of new users, which is also the most it can grow to
+    pub sigma: f32,
+    /// Spread of performances around a rating
+    pub beta: f32,
+    /// How much uncertainty is added each stream
+    pub tau: f32,
+    /// How many games a stream counts as. The games against the sampled
+    /// opponents are weighted to add up to this, so the number of
+    /// opponents doesn't change how much a stream counts.
+    pub games_per_stream: f32,
+}
+
+impl Default for OpenSkill {
+    fn default() -> Self {
+        /* OpenSkill's defaults (mu 25, sigma 25/3, beta sigma/2,
+        tau mu/300), scaled by 48 to centre on 1200 */
+        Self {
+            sigma: 400.0,
+            beta: 200.0,
+            tau: 4.0,
+            games_per_stream: 10.0,
+        }
+    }
+}
+
+impl OpenSkill {
+    fn sigma(&self, rating: &Rating) -> f64 {
+        rating.deviation.unwrap_or(self.sigma) as f64
+    }
+
+    /// Chance of the user winning, and the spread of the game
+    fn probability(&self, rating: &Rating, opponent: &Rating) -> (f64, f64) {
+        let sigma = self.sigma(rating);
+        let opponent_sigma = self.sigma(opponent);
+        let beta = self.beta as f64;
+        let c = (sigma * sigma + opponent_sigma * opponent_sigma + 2.0 * beta * beta).sqrt();
+        let p = 1.0 / (1.0 + ((opponent.rating as f64 - rating.rating as f64) / c).exp());
+        (p, c)
+    }
+
+    fn with_sigma(&self, mu: f64, sigma: f64) -> Rating {
+        Rating {
+            rating: mu as f32,
+            deviation: Some((sigma as f32).min(self.sigma)),
+            volatility: None,
+        }
+    }
+}
+
+impl RatingSystem for OpenSkill {
+    fn get_name(&self) -> String {
+        "openskill".to_string()
+    }
+
+    fn initial_rating(&self) -> Rating {
+        Rating {
+            rating: INITIAL_ELO,
+            deviation: Some(self.sigma),
+            volatility: None,
+        }
+    }
+
+    fn expected_outcome(&self, rating: &Rating, opponent: &Rating) -> f32 {
+        self.probability(rating, opponent).0 as f32
+    }
+
+    fn rate(&self, rating: Rating, games: &[Game]) -> Rating {
+        let rating = self.idle(rating);
+        if games.is_empty() {
+            return rating;
+        }
+
+        let weight = if self.games_per_stream > 0.0 {
+            self.games_per_stream as f64 / games.len() as f64
+        } else {
+            1.0
+        };
+        /* Each game is applied on its own, so the uncertainty shrinks as
+        the stream goes on instead of all games moving the rating by the
+        starting uncertainty */
+        let mut mu = rating.rating as f64;
+        let mut sigma = self.sigma(&rating);
+        for game in games {
+            let current = Rating {
+                rating: mu as f32,
+                deviation: Some(sigma as f32),
+                volatility: None,
+            };
+            let (p, c) = self.probability(&current, &game.opponent);
+            let variance = sigma * sigma;
+            let gamma = sigma / c;
+            mu += weight * variance / c * (game.outcome as f64 - p);
+            let delta = weight * gamma * variance / (c * c) * p * (1.0 - p);
+            sigma *= (1.0 - delta).max(KAPPA).sqrt();
+        }
+
+        self.with_sigma(mu, sigma)
+    }
+
+    /// The uncertainty grows by tau every stream
+    fn idle(&self, rating: Rating) -> Rating {
+        let sigma = self.sigma(&rating);
+        let tau = self.tau as f64;
+        self.with_sigma(rating.rating as f64, (sigma * sigma + tau * tau).sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(mu: f32, sigma: f32) -> Rating {
+        Rating {
+            rating: mu,
+            deviation: Some(sigma),
+            volatility: None,
+        }
+    }
+
+    fn game(opponent: Rating, outcome: f32) -> Game {
+        Game { opponent, outcome }
+    }
+
+    #[test]
+    fn equal_users_move_equally_apart() {
+        let openskill = OpenSkill::default();
+        let user = rating(1200.0, 300.0);
+
+        let winner = openskill.rate(user, &[game(user, 1.0)]);
+        let loser = openskill.rate(user, &[game(user, 0.0)]);
+
+        assert!(winner.rating > 1200.0);
+        assert!(loser.rating < 1200.0);
+        assert!((winner.rating - 1200.0 - (1200.0 - loser.rating)).abs() < 0.001);
+        assert_eq!(winner.deviation, loser.deviation);
+    }
+
+    #[test]
+    fn the_favourite_gains_less_for_winning() {
+        let openskill = OpenSkill::default();
+        let favourite = rating(1500.0, 300.0);
+        let underdog = rating(900.0, 300.0);
+
+        let favourite_gain = openskill.rate(favourite, &[game(underdog, 1.0)]).rating - 1500.0;
+        let underdog_gain = openskill.rate(underdog, &[game(favourite, 1.0)]).rating - 900.0;
+
+        assert!(favourite_gain > 0.0);
+        assert!(favourite_gain < underdog_gain);
+    }
+
+    #[test]
+    fn uncertainty_shrinks_when_rated_and_grows_while_idle() {
+        let openskill = OpenSkill::default();
+        let user = rating(1200.0, 300.0);
+
+        let rated = openskill.rate(user, &[game(user, 1.0), game(user, 0.0)]);
+        let idle = openskill.rate(user, &[]);
+
+        assert!(rated.deviation.unwrap() < 300.0);
+        assert!(idle.deviation.unwrap() > 300.0);
+        assert_eq!(idle.rating, 1200.0);
+
+        /* But never past the uncertainty of a new user */
+        let mut rating = user;
+        for _ in 0..100_000 {
+            rating = openskill.idle(rating);
+        }
+        assert_eq!(rating.deviation, Some(openskill.sigma));
+    }
+
+    #[test]
+    fn uncertainty_stays_positive_after_many_games() {
+        let openskill = OpenSkill {
+            games_per_stream: 0.0,
+            ..Default::default()
+        };
+        let favourite = rating(3000.0, 10.0);
+        let underdog = rating(-500.0, 400.0);
+
+        for (user, opponent, outcome) in [
+            (underdog, favourite, 1.0),
+            (favourite, underdog, 0.0),
+            (rating(1200.0, 400.0), rating(1200.0, 400.0), 1.0),
+        ] {
+            let rated = openskill.rate(user, &vec![game(opponent, outcome); 10_000]);
+            assert!(rated.is_finite(), "{:?}", rated);
+            assert!(rated.deviation.unwrap() > 0.0, "{:?}", rated);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Represents a rating system
+use std::fmt::Debug;
+
+/// A user's rating, along with any extra state the rating system keeps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub rating: f32,
+    /// How unsure the rating system is of the rating
+    pub deviation: Option<f32>,
+    /// How erratic the user's performances are
+    pub volatility: Option<f32>,
+}
+
+impl Rating {
+    /// Whether every part of the rating is a number, and not infinite
+    pub fn is_finite(&self) -> bool {
+        self.rating.is_finite()
+            && self.deviation.is_none_or(f32::is_finite)
+            && self.volatility.is_none_or(f32::is_finite)
+    }
+}
+
+/// A user's result against one of the sampled opponents
+#[derive(Debug, Clone, Copy)]
+pub struct Game {
+    pub opponent: Rating,
+    /// 1 for a win, 0 for a loss
+    pub outcome: f32,
+}
+
+/// The opponents sampled for a stream, sorted by score so a user's wins
+/// can be counted with a binary search
+#[derive(Debug, Clone, Default)]
+pub struct Opponents {
+    scores: Vec<f32>,
+    ratings: Vec<Rating>,
+    /// `10^(rating / 400)` of every opponent, so Elo's expected outcomes
+    /// don't need a power per game
+    elo_powers: Vec<f64>,
+}
+
+impl Opponents {
+    pub fn new(mut opponents: Vec<(f32, Rating)>) -> Self {
+        opponents.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let (scores, ratings): (Vec<f32>, Vec<Rating>) = opponents.into_iter().unzip();
+        let elo_powers = ratings
+            .iter()
+            .map(|rating| 10.0_f64.powf(rating.rating as f64 / 400.0))
+            .collect();
+        Self {
+            scores,
+            ratings,
+            elo_powers,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// The (score, rating) of every opponent, lowest score first
+    pub fn iter(&self) -> impl Iterator<Item = (f32, &Rating)> {
+        self.scores.iter().copied().zip(self.ratings.iter())
+    }
+
+    pub fn ratings(&self) -> &[Rating] {
+        &self.ratings
+    }
+
+    pub fn elo_powers(&self) -> &[f64] {
+        &self.elo_powers
+    }
+
+    /// How many opponents a user with `score` beats, which are the first
+    /// that many opponents
+    pub fn wins(&self, score: f32) -> usize {
+        self.scores.partition_point(|opponent| *opponent < score)
+    }
+
+    /// The games of a user who beat the first `wins` opponents and lost
+    /// to the rest
+    pub fn games(&self, wins: usize) -> Vec<Game> {
+        self.ratings
+            .iter()
+            .enumerate()
+            .map(|(index, opponent)| Game {
+                opponent: *opponent,
+                outcome: (index < wins) as u8 as f32,
+            })
+            .collect()
+    }
+}
+
+pub trait RatingSystem: Debug + Send + Sync {
+    /*
+    Structs that implement this trait turn the results of a stream into
+    new ratings.
+
+    Each stream, every user who chatted plays one game against each of
+    the opponents sampled from the leaderboard, winning if they scored
+    higher. Systems should fall back to their initial deviation or
+    volatility for ratings that don't have one, e.g. ratings from a
+    leaderboard that used a different system.
+    */
+
+    fn get_name(&self) -> String;
+
+    /// The rating users start with
+    fn initial_rating(&self) -> Rating;
+
+    /// Chance of a user with `rating` beating `opponent`
+    fn expected_outcome(&self, rating: &Rating, opponent: &Rating) -> f32;
+
+    /// The rating of a user after playing a stream's games
+    fn rate(&self, rating: Rating, games: &[Game]) -> Rating;
+
+    /// Sum of the chances of a user with `rating` beating each opponent
+    fn expected_wins(&self, rating: &Rating, opponents: &Opponents) -> f32 {
+        opponents
+            .ratings()
+            .iter()
+            .map(|opponent| self.expected_outcome(rating, opponent))
+            .sum()
+    }
+
+    /// The rating of a user after beating the first `wins` opponents and
+    /// losing to the rest. Systems can override this to avoid building
+    /// the games.
+    fn rate_against(&self, rating: Rating, opponents: &Opponents, wins: usize) -> Rating {
+        self.rate(rating, &opponents.games(wins))
+    }
+
+    /// The rating of a user who didn't play this stream
+    fn idle(&self, rating: Rating) -> Rating {
+        rating
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users_only_beat_opponents_with_lower_scores() {
+        let rating = Rating {
+            rating: 1200.0,
+            deviation: None,
+            volatility: None,
+        };
+        let opponents = Opponents::new(vec![
+            (3.0, rating),
+            (1.0, rating),
+            (2.0, rating),
+            (2.0, rating),
+        ]);
+
+        assert_eq!(opponents.wins(0.0), 0);
+        assert_eq!(opponents.wins(1.0), 0);
+        assert_eq!(opponents.wins(2.0), 1);
+        assert_eq!(opponents.wins(2.5), 3);
+        assert_eq!(opponents.wins(10.0), 4);
+        let games = opponents.games(opponents.wins(2.5));
+        let outcomes: Vec<f32> = games.iter().map(|game| game.outcome).collect();
+        assert_eq!(outcomes, [1.0, 1.0, 1.0, 0.0]);
+    }
+}
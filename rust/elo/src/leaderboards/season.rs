@@ -0,0 +1,278 @@
+//! Seasons, which each keep their own leaderboards under `seasons/{id}/`
+//!
+//! When a new season starts, the standings of the previous one are
+//! archived to `seasons/{id}/final/`, and can be used to seed the ratings
+//! of the new season. The seeded leaderboards are also kept in
+//! `seasons/{id}/seed/`, so the season can be recomputed from them.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use prost::Message;
+
+use crate::_types::leaderboardtypes::LeaderboardExport;
+use crate::leaderboards::history::is_history_path;
+use crate::leaderboards::leaderboardtrait::INITIAL_ELO;
+use crate::leaderboards::store::checked::{read_checked, write_checked, DEFAULT_BACKUPS};
+use crate::leaderboards::store::filesystem::FileStore;
+use crate::leaderboards::store::storetrait::LeaderboardStore;
+
+/// Where the seasons are kept, inside the output directory
+pub const SEASONS_DIRECTORY: &str = "seasons";
+
+/// Records the season that was last started
+const CURRENT_SEASON_FILE: &str = "current.txt";
+
+/// Where a season's final standings are archived, inside its directory
+const ARCHIVE_DIRECTORY: &str = "final";
+
+/// Where the leaderboards a season was seeded with are kept, inside its
+/// directory
+const SEED_DIRECTORY: &str = "seed";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Season {
+    id: String,
+    /// The directory the seasons are kept in
+    root: PathBuf,
+}
+
+impl Season {
+    /// The id is used as a directory name, so it can only contain
+    /// letters, digits, `.`, `-` and `_`
+    pub fn new(id: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let id = id.trim();
+        let valid_characters = id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+        if id.is_empty() || id == "." || id == ".." || !valid_characters {
+            return Err(format!("Invalid season id {:?}", id).into());
+        }
+        Ok(Self {
+            id: id.to_string(),
+            root: PathBuf::from(SEASONS_DIRECTORY),
+        })
+    }
+
+    /// Keeps the seasons under `{directory}/seasons/` instead of the
+    /// working directory
+    pub fn in_directory(mut self, directory: &Path) -> Self {
+        self.root = directory.join(SEASONS_DIRECTORY);
+        self
+    }
+
+    /// Reads the season id from a file, like the repository's `season.txt`
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new(&fs::read_to_string(path)?)
+    }
+
+    /// The season that was last started alongside this one, if any
+    pub fn current(&self) -> Option<Self> {
+        let path = self.root.join(CURRENT_SEASON_FILE);
+        let id = fs::read_to_string(path).ok()?;
+        Some(Self {
+            id: Self::new(&id).ok()?.id,
+            root: self.root.clone(),
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Where the season's leaderboards are saved
+    pub fn directory(&self) -> PathBuf {
+        self.root.join(&self.id)
+    }
+
+    /// A store for the season's leaderboards
+    pub fn store(&self) -> FileStore {
+        FileStore::new(self.directory())
+    }
+
+    /// Where the season's final standings are archived
+    pub fn archive_directory(&self) -> PathBuf {
+        self.directory().join(ARCHIVE_DIRECTORY)
+    }
+
+    /// The leaderboards the season started with, if it was seeded from
+    /// the previous one
+    pub fn seed_store(&self) -> FileStore {
+        FileStore::new(self.directory().join(SEED_DIRECTORY))
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.archive_directory().exists()
+    }
+
+    /// Copies the season's leaderboards to its archive, as read-only files.
+    /// A season can only be archived once.
+    pub fn archive(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.is_archived() {
+            return Err(format!("Season {} is already archived", self.id).into());
+        }
+        /* Copy into a temporary directory first, so a failed archive
+        doesn't leave a partial one behind */
+        let partial = self
+            .directory()
+            .join(format!("{}.partial", ARCHIVE_DIRECTORY));
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+        fs::create_dir_all(&partial)?;
+        for path in leaderboard_files(&self.directory())? {
+            /* The checksums go along, so the archive can be verified */
+            let mut checksum = path.clone().into_os_string();
+            checksum.push(".sha256");
+            for path in [path, PathBuf::from(checksum)] {
+                if !path.exists() {
+                    continue;
+                }
+                let archived =
+                    partial.join(path.file_name().expect("Leaderboard files have names"));
+                fs::copy(&path, &archived)?;
+                let mut permissions = fs::metadata(&archived)?.permissions();
+                permissions.set_readonly(true);
+                fs::set_permissions(&archived, permissions)?;
+            }
+        }
+        fs::rename(&partial, self.archive_directory())?;
+        info!(
+            "Season {} archived to {:?}",
+            self.id,
+            self.archive_directory()
+        );
+        Ok(())
+    }
+
+    /// Makes this the current season, if it isn't already.
+    ///
+    /// The previous season is archived if it wasn't already. With
+    /// `soft_reset`, every user starts the season with the rating they
+    /// ended the previous one with, moved `soft_reset` (0 to 1) of the way
+    /// back to the initial elo. Otherwise everyone starts over.
+    pub fn start(&self, soft_reset: Option<f32>) -> Result<(), Box<dyn std::error::Error>> {
+        let previous = self.current();
+        if previous.as_ref() == Some(self) {
+            return Ok(());
+        }
+
+        fs::create_dir_all(self.directory())?;
+        if let Some(previous) = previous {
+            info!(
+                "Season {} is over, starting season {}",
+                previous.id, self.id
+            );
+            if !previous.is_archived() {
+                previous.archive()?;
+            }
+            if let Some(soft_reset) = soft_reset {
+                self.seed(&previous, soft_reset)?;
+            }
+        } else {
+            info!("Starting season {}", self.id);
+        }
+
+        fs::write(self.root.join(CURRENT_SEASON_FILE), &self.id)?;
+        Ok(())
+    }
+
+    /// Creates this season's leaderboards from the final standings of
+    /// another season, leaving any that already exist alone. The history
+    /// of the previous season isn't carried over.
+    fn seed(&self, previous: &Season, soft_reset: f32) -> Result<(), Box<dyn std::error::Error>> {
+        let store = self.store();
+        let archive = FileStore::new(previous.archive_directory());
+        for path in leaderboard_files(&previous.archive_directory())? {
+            if is_history_path(&path) {
+                continue;
+            }
+            let key = path
+                .file_name()
+                .expect("Leaderboard files have names")
+                .to_string_lossy()
+                .to_string();
+            if store.read(&key)?.is_some() {
+                continue;
+            }
+
+            let Some(archived) = read_checked(&archive, &key, 0, |data| {
+                LeaderboardExport::decode(data).is_ok()
+            })?
+            else {
+                continue;
+            };
+            let mut leaderboard = LeaderboardExport::decode(&*archived)?;
+            for item in leaderboard.items.iter_mut() {
+                item.elo -= soft_reset * (item.elo - INITIAL_ELO);
+                /* The rating system starts the uncertainty over */
+                item.deviation = None;
+                item.volatility = None;
+                item.rated_streams = Some(0);
+                item.missed_streams = 0;
+                item.provisional = false;
+                item.inactive = false;
+                item.delta = 0;
+            }
+            /* It wasn't generated by a run of this season */
+            leaderboard.header = None;
+            write_checked(&self.seed_store(), &key, &leaderboard.encode_to_vec(), 0)?;
+            write_checked(&store, &key, &leaderboard.encode_to_vec(), DEFAULT_BACKUPS)?;
+            info!(
+                "Seeded {} of season {} from season {}",
+                key, self.id, previous.id
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The `.bin` leaderboard files in a directory
+fn leaderboard_files(directory: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    if !directory.exists() {
+        return Ok(files);
+    }
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|extension| extension == "bin") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::_types::leaderboardtypes::LeaderboardExportItem;
+
+    #[test]
+    fn archived_boards_keep_their_checksums_and_seed_the_next_season() {
+        let directory = tempfile::tempdir().unwrap();
+        let first = Season::new("1").unwrap().in_directory(directory.path());
+        first.start(None).unwrap();
+        let board = LeaderboardExport::from(vec![LeaderboardExportItem {
+            id: "user".to_string(),
+            elo: 1400.0,
+            rated_streams: Some(5),
+            ..Default::default()
+        }]);
+        write_checked(&first.store(), "test.bin", &board.encode_to_vec(), 0).unwrap();
+
+        let second = Season::new("2").unwrap().in_directory(directory.path());
+        second.start(Some(0.5)).unwrap();
+
+        let archive = FileStore::new(first.archive_directory());
+        assert!(archive.read("test.bin.sha256").unwrap().is_some());
+        let seeded = second.store().read("test.bin").unwrap().unwrap();
+        let seeded = LeaderboardExport::decode(&*seeded).unwrap();
+        assert_eq!(seeded.items[0].elo, 1300.0);
+        assert_eq!(seeded.items[0].rated_streams, Some(0));
+        assert_eq!(
+            second.seed_store().read("test.bin").unwrap().unwrap(),
+            second.store().read("test.bin").unwrap().unwrap()
+        );
+    }
+}
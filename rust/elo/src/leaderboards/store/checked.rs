@@ -0,0 +1,318 @@
+//! Saves leaderboards so that a crash or a truncated cache restore can't
+//! lose them.
+//!
+//! Every save also writes a SHA-256 checksum next to the leaderboard, and
+//! keeps the previous versions under `backups/`. Loading checks the
+//! checksum, and falls back to the newest good backup if it doesn't match.
+//!
+//! The checksum is written before the file and lists both the new and the
+//! old checksum until the file is written, so a crash between the two
+//! writes leaves a file that matches either way.
+use log::{debug, error, warn};
+use sha2::{Digest, Sha256};
+
+use crate::leaderboards::store::storetrait::{join_key, LeaderboardStore};
+
+/// Previous versions kept of every leaderboard, unless it sets its own
+pub const DEFAULT_BACKUPS: usize = 3;
+
+pub fn checksum(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Whether data matches one of the checksums in a `.sha256` file
+pub fn matches_checksum(expected: &str, data: &[u8]) -> bool {
+    let actual = checksum(data);
+    expected.lines().any(|line| line.trim() == actual)
+}
+
+fn checksum_key(key: &str) -> String {
+    format!("{}.sha256", key)
+}
+
+/// The key of the `number`th newest backup, from 1
+pub fn backup_key(key: &str, number: usize) -> String {
+    join_key("backups", &format!("{}.{}", key, number))
+}
+
+/// How the contents of a file compare to its checksum
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Verified {
+    Matches,
+    Mismatch,
+    /// Saved before checksums were added
+    NoChecksum,
+}
+
+fn verify(
+    store: &dyn LeaderboardStore,
+    key: &str,
+    data: &[u8],
+) -> Result<Verified, Box<dyn std::error::Error>> {
+    match store.read(&checksum_key(key))? {
+        Some(expected) if matches_checksum(&String::from_utf8_lossy(&expected), data) => {
+            Ok(Verified::Matches)
+        }
+        Some(_) => {
+            warn!("{} doesn't match its checksum", key);
+            Ok(Verified::Mismatch)
+        }
+        None => {
+            debug!("{} has no checksum", key);
+            Ok(Verified::NoChecksum)
+        }
+    }
+}
+
+/// Reads a file, if it exists and doesn't contradict its checksum
+fn read_verified(
+    store: &dyn LeaderboardStore,
+    key: &str,
+) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let Some(data) = store.read(key)? else {
+        return Ok(None);
+    };
+    Ok((verify(store, key, &data)? != Verified::Mismatch).then_some(data))
+}
+
+fn write_with_checksum(
+    store: &dyn LeaderboardStore,
+    key: &str,
+    data: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let new = checksum(data);
+    /* Both checksums are listed while the file is written, so it matches
+    whether or not the write went through */
+    let mut listed = new.clone();
+    if let Some(old) = store.read(&checksum_key(key))? {
+        if let Some(old) = String::from_utf8_lossy(&old).lines().next() {
+            listed = format!("{}\n{}", new, old.trim());
+        }
+    }
+    store.write(&checksum_key(key), listed.as_bytes())?;
+    store.write(key, data)?;
+    store.write(&checksum_key(key), new.as_bytes())?;
+    Ok(())
+}
+
+/// Saves a file with its checksum, moving the previous version to the
+/// backups and dropping the oldest backup
+pub fn write_checked(
+    store: &dyn LeaderboardStore,
+    key: &str,
+    data: &[u8],
+    backups: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if backups > 0 {
+        /* A broken file isn't backed up, so it can't push out good backups */
+        if let Some(previous) = read_verified(store, key)? {
+            for number in (1..backups).rev() {
+                if let Some(backup) = read_verified(store, &backup_key(key, number))? {
+                    write_with_checksum(store, &backup_key(key, number + 1), &backup)?;
+                }
+            }
+            write_with_checksum(store, &backup_key(key, 1), &previous)?;
+        }
+    }
+    write_with_checksum(store, key, data)
+}
+
+/// Reads a file saved with `write_checked`.
+///
+/// If the file is missing, doesn't match its checksum or isn't `valid`,
+/// the newest backup that is fine is used instead. A file without a
+/// checksum is only used if no copy has a matching one, which is the case
+/// for leaderboards saved before checksums were added; saving it again
+/// adds the checksum. `None` means nothing was ever saved, and an error
+/// that something was but none of it can be used.
+pub fn read_checked(
+    store: &dyn LeaderboardStore,
+    key: &str,
+    backups: usize,
+    valid: impl Fn(&[u8]) -> bool,
+) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let mut found = false;
+    let mut unchecked = None;
+    for (number, candidate) in candidates(key, backups).enumerate() {
+        let Some(data) = store.read(&candidate)? else {
+            continue;
+        };
+        found = true;
+        let verified = verify(store, &candidate, &data)?;
+        if verified == Verified::Mismatch || !valid(&data) {
+            warn!("{} is broken", candidate);
+            continue;
+        }
+        if verified == Verified::NoChecksum {
+            unchecked.get_or_insert((candidate, data));
+            continue;
+        }
+        if number > 0 {
+            error!(
+                "{} is missing or broken, RESTORED IT FROM BACKUP {}. \
+                Updates saved since then are lost.",
+                key, candidate
+            );
+        }
+        return Ok(Some(data));
+    }
+
+    if let Some((candidate, data)) = unchecked {
+        warn!("{} has no checksum, trusting it this once", candidate);
+        return Ok(Some(data));
+    }
+    if found {
+        return Err(format!("{} and all of its backups are broken", key).into());
+    }
+    Ok(None)
+}
+
+/// A file and its backups, newest first
+fn candidates(key: &str, backups: usize) -> impl Iterator<Item = String> + '_ {
+    std::iter::once(key.to_string()).chain((1..=backups).map(move |number| backup_key(key, number)))
+}
+
+/// Where `set_aside` moves a broken file
+pub fn broken_key(key: &str) -> String {
+    join_key("broken", key)
+}
+
+/// Moves a file and its backups to `broken/` along with their checksums,
+/// so that a leaderboard none of which can be read starts over without
+/// losing what is left of it
+pub fn set_aside(
+    store: &dyn LeaderboardStore,
+    key: &str,
+    backups: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for candidate in candidates(key, backups) {
+        for file in [candidate.clone(), checksum_key(&candidate)] {
+            if let Some(data) = store.read(&file)? {
+                store.write(&broken_key(&file), &data)?;
+                store.remove(&file)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::leaderboards::store::memory::MemoryStore;
+
+    /// Fails every write after the first `writes`, like a run that crashed
+    #[derive(Debug)]
+    struct CrashingStore {
+        inner: MemoryStore,
+        writes: AtomicUsize,
+    }
+
+    impl LeaderboardStore for CrashingStore {
+        fn read(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+            self.inner.read(key)
+        }
+
+        fn write(&self, key: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+            if self.writes.load(Ordering::SeqCst) == 0 {
+                return Err("crashed".into());
+            }
+            self.writes.fetch_sub(1, Ordering::SeqCst);
+            self.inner.write(key, data)
+        }
+
+        fn remove(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+            self.inner.remove(key)
+        }
+
+        fn list(&self, directory: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+            self.inner.list(directory)
+        }
+    }
+
+    fn read(store: &dyn LeaderboardStore) -> Option<Vec<u8>> {
+        read_checked(store, "test.bin", 2, |_| true).unwrap()
+    }
+
+    #[test]
+    fn a_crash_while_saving_leaves_the_old_or_the_new_version() {
+        for writes in 0.. {
+            let inner = MemoryStore::new();
+            write_checked(&inner, "test.bin", b"old", 2).unwrap();
+            let store = CrashingStore {
+                inner: inner.clone(),
+                writes: AtomicUsize::new(writes),
+            };
+
+            let saved = write_checked(&store, "test.bin", b"new", 2).is_ok();
+
+            let data = read(&inner).unwrap();
+            assert!(
+                data == b"old" || data == b"new",
+                "crashed after {} writes",
+                writes
+            );
+            assert_eq!(inner.read("test.bin").unwrap().unwrap(), data);
+            if saved {
+                assert_eq!(data, b"new");
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn a_broken_file_is_restored_from_the_newest_good_backup() {
+        let store = MemoryStore::new();
+        for data in [b"one", b"two", b"tri"] {
+            write_checked(&store, "test.bin", data, 2).unwrap();
+        }
+        store.write("test.bin", b"truncated").unwrap();
+        assert_eq!(read(&store).unwrap(), b"two");
+
+        store
+            .write(&backup_key("test.bin", 1), b"truncated")
+            .unwrap();
+        assert_eq!(read(&store).unwrap(), b"one");
+    }
+
+    #[test]
+    fn a_file_without_a_checksum_is_only_trusted_without_a_good_backup() {
+        let store = MemoryStore::new();
+        store.write("test.bin", b"legacy").unwrap();
+        assert_eq!(read(&store).unwrap(), b"legacy");
+
+        write_checked(&store, "test.bin", b"checked", 2).unwrap();
+        assert!(store.read("test.bin.sha256").unwrap().is_some());
+        assert_eq!(read(&store).unwrap(), b"checked");
+
+        store.remove("test.bin.sha256").unwrap();
+        store.write("test.bin", b"unchecked").unwrap();
+        assert_eq!(read(&store).unwrap(), b"legacy");
+    }
+
+    #[test]
+    fn broken_files_are_set_aside() {
+        let store = MemoryStore::new();
+        write_checked(&store, "test.bin", b"one", 2).unwrap();
+        write_checked(&store, "test.bin", b"two", 2).unwrap();
+        store.write("test.bin", b"truncated").unwrap();
+        store
+            .write(&backup_key("test.bin", 1), b"truncated")
+            .unwrap();
+        assert!(read_checked(&store, "test.bin", 2, |_| true).is_err());
+
+        set_aside(&store, "test.bin", 2).unwrap();
+
+        assert_eq!(read(&store), None);
+        assert_eq!(
+            store.read(&broken_key("test.bin")).unwrap().unwrap(),
+            b"truncated"
+        );
+        assert!(store
+            .read(&broken_key("test.bin.sha256"))
+            .unwrap()
+            .is_some());
+    }
+}
@@ -0,0 +1,113 @@
+//! Keeps leaderboards as files under a directory
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::leaderboards::store::storetrait::{join_key, LeaderboardStore};
+
+#[derive(Debug, Clone, Default)]
+pub struct FileStore {
+    /// Relative to the working directory unless absolute. Empty is the
+    /// working directory itself.
+    root: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+impl LeaderboardStore for FileStore {
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        match fs::read(self.path(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes to a temporary file that then replaces the file, so a crash
+    /// leaves either the old or the new file and never half of one
+    fn write(&self, key: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.path(key);
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(format!(".{}.tmp", std::process::id()));
+        let temporary = PathBuf::from(temporary);
+
+        let mut file = File::create(&temporary)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self, directory: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let path = self.path(directory);
+        let path = if path.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            path.as_path()
+        };
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                keys.push(join_key(directory, &entry.file_name().to_string_lossy()));
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_round_trip_and_list_by_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = FileStore::new(directory.path());
+        store.write("a.bin", b"a").unwrap();
+        store.write("history/a/1.bin", b"1").unwrap();
+        store.write("history/a/1.bin", b"replaced").unwrap();
+
+        assert_eq!(store.read("history/a/1.bin").unwrap().unwrap(), b"replaced");
+        assert_eq!(store.read("b.bin").unwrap(), None);
+        assert_eq!(store.list("").unwrap(), ["a.bin"]);
+        assert_eq!(store.list("history/a").unwrap(), ["history/a/1.bin"]);
+        assert!(store.list("pages").unwrap().is_empty());
+
+        store.remove("history/a/1.bin").unwrap();
+        store.remove("missing.bin").unwrap();
+        assert!(store.list("history/a").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+//! Keeps leaderboards in memory, for tests and tools that shouldn't touch
+//! the disk
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::leaderboards::store::storetrait::LeaderboardStore;
+
+/// Clones share the same files, so a clone can be kept to look at what
+/// the leaderboards saved
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    files: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keys of every file, in every directory, sorted
+    pub fn keys(&self) -> Vec<String> {
+        self.files.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+impl LeaderboardStore for MemoryStore {
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        Ok(self.files.lock().unwrap().get(key).cloned())
+    }
+
+    fn write(&self, key: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.files.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn list(&self, directory: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let prefix = if directory.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", directory.trim_end_matches('/'))
+        };
+        Ok(self
+            .files
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|key| {
+                key.strip_prefix(&prefix)
+                    .is_some_and(|name| !name.contains('/'))
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_round_trip_and_list_by_directory() {
+        let store = MemoryStore::new();
+        store.write("a.bin", b"a").unwrap();
+        store.write("history/a/1.bin", b"1").unwrap();
+        store.write("history/a/2.bin", b"2").unwrap();
+        store.write("history/ab/1.bin", b"other").unwrap();
+
+        assert_eq!(store.read("history/a/2.bin").unwrap().unwrap(), b"2");
+        assert_eq!(store.read("b.bin").unwrap(), None);
+        assert_eq!(store.list("").unwrap(), ["a.bin"]);
+        assert_eq!(
+            store.list("history/a/").unwrap(),
+            ["history/a/1.bin", "history/a/2.bin"]
+        );
+
+        store.remove("history/a/1.bin").unwrap();
+        store.remove("missing.bin").unwrap();
+        assert_eq!(store.list("history/a").unwrap(), ["history/a/2.bin"]);
+        assert_eq!(store.clone().keys().len(), 3);
+    }
+}
@@ -0,0 +1,5 @@
+pub mod checked;
+pub mod filesystem;
+pub mod memory;
+pub mod overlay;
+pub mod storetrait;
@@ -0,0 +1,106 @@
+//! Reads through to another store, but keeps writes to itself. Lets
+//! leaderboard updates be tried out without saving them.
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+use crate::leaderboards::store::memory::MemoryStore;
+use crate::leaderboards::store::storetrait::LeaderboardStore;
+
+#[derive(Debug, Clone)]
+pub struct OverlayStore {
+    base: Arc<dyn LeaderboardStore>,
+    changes: MemoryStore,
+    /// Files removed from the base store, until they are written again
+    removed: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl OverlayStore {
+    pub fn new(base: Arc<dyn LeaderboardStore>) -> Self {
+        Self {
+            base,
+            changes: MemoryStore::new(),
+            removed: Arc::default(),
+        }
+    }
+
+    /// The files that were written, which the base store doesn't have
+    pub fn changes(&self) -> &MemoryStore {
+        &self.changes
+    }
+
+    /// The files of the base store that were removed
+    pub fn removed(&self) -> Vec<String> {
+        self.removed.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl LeaderboardStore for OverlayStore {
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        match self.changes.read(key)? {
+            Some(data) => Ok(Some(data)),
+            None if self.removed.lock().unwrap().contains(key) => Ok(None),
+            None => self.base.read(key),
+        }
+    }
+
+    fn write(&self, key: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.removed.lock().unwrap().remove(key);
+        self.changes.write(key, data)
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.removed.lock().unwrap().insert(key.to_string());
+        self.changes.remove(key)
+    }
+
+    fn list(&self, directory: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let removed = self.removed.lock().unwrap().clone();
+        let mut keys = self.base.list(directory)?;
+        keys.retain(|key| !removed.contains(key));
+        keys.extend(self.changes.list(directory)?);
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_and_removals_stay_in_the_overlay() {
+        let base = MemoryStore::new();
+        base.write("a.bin", b"base").unwrap();
+        base.write("pages/a/1.bin", b"page").unwrap();
+        let overlay = OverlayStore::new(Arc::new(base.clone()));
+
+        overlay.write("a.bin", b"changed").unwrap();
+        overlay.write("b.bin", b"new").unwrap();
+        overlay.remove("pages/a/1.bin").unwrap();
+
+        assert_eq!(overlay.read("a.bin").unwrap().unwrap(), b"changed");
+        assert_eq!(overlay.read("b.bin").unwrap().unwrap(), b"new");
+        assert_eq!(overlay.read("pages/a/1.bin").unwrap(), None);
+        assert_eq!(overlay.list("").unwrap(), ["a.bin", "b.bin"]);
+        assert!(overlay.list("pages/a").unwrap().is_empty());
+        assert_eq!(overlay.removed(), ["pages/a/1.bin"]);
+
+        assert_eq!(base.read("a.bin").unwrap().unwrap(), b"base");
+        assert_eq!(base.read("b.bin").unwrap(), None);
+        assert!(base.read("pages/a/1.bin").unwrap().is_some());
+    }
+
+    #[test]
+    fn writing_a_removed_file_brings_it_back() {
+        let base = MemoryStore::new();
+        base.write("a.bin", b"base").unwrap();
+        let overlay = OverlayStore::new(Arc::new(base));
+
+        overlay.remove("a.bin").unwrap();
+        overlay.write("a.bin", b"again").unwrap();
+
+        assert_eq!(overlay.read("a.bin").unwrap().unwrap(), b"again");
+        assert!(overlay.removed().is_empty());
+    }
+}
@@ -0,0 +1,29 @@
+use std::fmt::Debug;
+
+/// Where leaderboards and the files saved next to them are kept.
+///
+/// Files are named by keys like `overall.bin` or `history/overall/1.bin`,
+/// with `/` separating directories.
+pub trait LeaderboardStore: Debug + Send + Sync {
+    /// The contents of a file, or `None` if it doesn't exist
+    fn read(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>>;
+
+    /// Creates or replaces a file
+    fn write(&self, key: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Deletes a file, if it exists
+    fn remove(&self, key: &str) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Keys of the files directly in a directory, sorted. `""` lists the
+    /// top level.
+    fn list(&self, directory: &str) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+}
+
+/// The key of a file in a directory
+pub fn join_key(directory: &str, name: &str) -> String {
+    if directory.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", directory.trim_end_matches('/'), name)
+    }
+}
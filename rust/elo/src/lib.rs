@@ -1,55 +1,228 @@
 use std::{
-    collections::HashMap, sync::{atomic::AtomicU32, Arc}
+    collections::HashMap,
+    sync::{atomic::AtomicU32, Arc},
 };
 
 use _types::clptypes::{Message, MetadataTypes, MetadataUpdate, MetricUpdate, UserChatPerformance};
+use fanout::{FanOut, FanOutError};
 use log::{debug, warn};
-use metadata::setup_metadata_and_channels;
-use metrics::setup_metrics_and_channels;
+use metadata::{metadatatrait::AbstractMetadata, setup_metadata_and_channels, MetadataRegistry};
+use metrics::{metrictrait::AbstractMetric, setup_metrics_and_channels, MetricRegistry};
+use scoring::ScoringConfig;
 use tokio::{
-    sync::{ mpsc, broadcast, oneshot }, 
-    task::JoinSet
+    sync::{mpsc, oneshot},
+    task::JoinSet,
 };
-use twitch_utils::{seventvclient::SevenTVClient, TwitchAPIWrapper};
+use twitch_utils::{datasource::ChatDataSource, seventvclient::SevenTVClient};
 
 pub mod _constants;
 pub mod _types;
+#[cfg(feature = "sqlite")]
+pub mod database;
+pub mod fanout;
 pub mod leaderboards;
 pub mod metadata;
 pub mod metrics;
+pub mod scoring;
 
 /// Struct to setup a MessageProcessorRunning and spawn metric/metadata processors
 /// 
 /// Call .start() to spawn tasks and get a `MessageProcessorRunning` struct
 pub struct MessageProcessorSetup {
+    fan_out: FanOut<(Arc<Message>, u32)>,
     metric_processor: metrics::MetricProcessor,
-    metric_sender: broadcast::Sender<(Message, u32)>,
-    metric_receiver: mpsc::Receiver<MetricUpdate>,
+    metric_receiver: mpsc::Receiver<(MetricUpdate, u32)>,
     metadata_processor: metadata::MetadataProcessor,
-    metadata_sender: broadcast::Sender<(Message, u32)>,
-    metadata_receiver: mpsc::Receiver<MetadataUpdate>,
+    metadata_receiver: mpsc::Receiver<(MetadataUpdate, u32)>,
 }
 
-impl MessageProcessorSetup {
-    pub async fn new(
-        twitch: &TwitchAPIWrapper,
-        seventv_client: Arc<SevenTVClient>,
-    ) -> Self {
-        let (metric_processor, metric_sender, metric_receiver) =
-            setup_metrics_and_channels(seventv_client.clone());
+/// Builder for a `MessageProcessorSetup`
+///
+/// Starts out with all of the built-in metrics and metadata registered.
+/// Built-ins are only constructed on `.build()`, so disabling them
+/// avoids any API calls they would make, and they are scored with
+/// whichever `ScoringConfig` is set by then.
+pub struct MessageProcessorSetupBuilder<'a> {
+    data_source: Option<&'a dyn ChatDataSource>,
+    seventv_client: Arc<SevenTVClient>,
+    scoring: ScoringConfig,
+    builtin_metrics: Vec<String>,
+    custom_metrics: Vec<Box<dyn AbstractMetric + Send>>,
+    metric_workers: usize,
+    builtin_metadata: Vec<String>,
+    custom_metadata: Vec<Box<dyn AbstractMetadata + Send>>,
+}
+
+impl<'a> MessageProcessorSetupBuilder<'a> {
+    /// Sets where metadata that needs Twitch data, e.g. `badges`, gets it from
+    pub fn data_source(mut self, data_source: &'a dyn ChatDataSource) -> Self {
+        self.data_source = Some(data_source);
+        self
+    }
+
+    /// Sets the weights and curves the built-in metrics are scored with
+    pub fn scoring(mut self, scoring: ScoringConfig) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    /// Registers a metric, replacing any metric with the same name
+    pub fn metric<M: AbstractMetric + Send + 'static>(mut self, metric: M) -> Self {
+        let name = metric.get_name();
+        self.builtin_metrics.retain(|builtin| *builtin != name);
+        self.custom_metrics
+            .retain(|custom| custom.get_name() != name);
+        self.custom_metrics.push(Box::new(metric));
+        self
+    }
+
+    /// Turns on a built-in metric by name
+    pub fn enable_metric(mut self, name: &str) -> Self {
+        if !metrics::BUILTIN_METRICS.contains(&name) {
+            warn!("No built-in metric named {}, ignoring", name);
+        } else if !self.builtin_metrics.iter().any(|builtin| builtin == name) {
+            self.custom_metrics
+                .retain(|custom| custom.get_name() != name);
+            self.builtin_metrics.push(name.to_string());
+        }
+        self
+    }
+
+    /// Turns off a metric by name
+    pub fn disable_metric(mut self, name: &str) -> Self {
+        let registered = self.builtin_metrics.len() + self.custom_metrics.len();
+        self.builtin_metrics.retain(|builtin| builtin != name);
+        self.custom_metrics
+            .retain(|custom| custom.get_name() != name);
+        if self.builtin_metrics.len() + self.custom_metrics.len() == registered {
+            warn!("Metric {} is not registered, ignoring", name);
+        }
+        self
+    }
+
+    /// Sets the number of workers each parallelizable metric is sharded
+    /// across. A value of 1 runs every metric sequentially.
+    pub fn metric_workers(mut self, workers: usize) -> Self {
+        self.metric_workers = workers;
+        self
+    }
 
-        let (metadata_processor, metadata_sender, metadata_receiver) =
-            setup_metadata_and_channels(twitch, seventv_client).await;
+    /// Removes every registered metric, including the built-ins
+    pub fn without_metrics(mut self) -> Self {
+        self.builtin_metrics.clear();
+        self.custom_metrics.clear();
+        self
+    }
 
-            MessageProcessorSetup {
+    /// Registers a piece of metadata, replacing any metadata with the same name
+    pub fn metadata<M: AbstractMetadata + Send + 'static>(mut self, metadata: M) -> Self {
+        let name = metadata.get_name();
+        self.builtin_metadata.retain(|builtin| *builtin != name);
+        self.custom_metadata
+            .retain(|custom| custom.get_name() != name);
+        self.custom_metadata.push(Box::new(metadata));
+        self
+    }
+
+    /// Turns on a built-in piece of metadata by name
+    pub fn enable_metadata(mut self, name: &str) -> Self {
+        if !metadata::BUILTIN_METADATA.contains(&name) {
+            warn!("No built-in metadata named {}, ignoring", name);
+        } else if !self.builtin_metadata.iter().any(|builtin| builtin == name) {
+            self.custom_metadata
+                .retain(|custom| custom.get_name() != name);
+            self.builtin_metadata.push(name.to_string());
+        }
+        self
+    }
+
+    /// Turns off a piece of metadata by name
+    pub fn disable_metadata(mut self, name: &str) -> Self {
+        self.builtin_metadata.retain(|builtin| builtin != name);
+        self.custom_metadata
+            .retain(|custom| custom.get_name() != name);
+        self
+    }
+
+    /// Removes every registered piece of metadata, including the built-ins
+    pub fn without_metadata(mut self) -> Self {
+        self.builtin_metadata.clear();
+        self.custom_metadata.clear();
+        self
+    }
+
+    pub async fn build(self) -> MessageProcessorSetup {
+        let mut fan_out = FanOut::new(fanout::DEFAULT_CAPACITY);
+
+        let mut metric_registry = MetricRegistry::new();
+        for name in self.builtin_metrics.iter() {
+            if let Some(metric) =
+                metrics::builtin_metric(name, self.seventv_client.clone(), &self.scoring)
+            {
+                metric_registry.register(metric);
+            }
+        }
+        for metric in self.custom_metrics {
+            metric_registry.register(metric);
+        }
+
+        let (metric_processor, metric_receiver) =
+            setup_metrics_and_channels(metric_registry, &mut fan_out);
+        let metric_processor = metric_processor.with_workers(self.metric_workers);
+
+        let mut metadata_registry = MetadataRegistry::new();
+        for name in self.builtin_metadata.iter() {
+            if let Some(metadata) =
+                metadata::builtin_metadata(name, self.data_source, self.seventv_client.clone())
+                    .await
+            {
+                metadata_registry.register(metadata);
+            }
+        }
+        for metadata in self.custom_metadata {
+            metadata_registry.register(metadata);
+        }
+
+        let (metadata_processor, metadata_receiver) =
+            setup_metadata_and_channels(metadata_registry, &mut fan_out);
+
+        MessageProcessorSetup {
+            fan_out,
             metric_processor,
-            metric_sender,
             metric_receiver,
             metadata_processor,
-            metadata_sender,
             metadata_receiver,
         }
     }
+}
+
+impl MessageProcessorSetup {
+    /// Sets up a message processor with all of the built-in metrics and metadata
+    pub async fn new(data_source: &dyn ChatDataSource, seventv_client: Arc<SevenTVClient>) -> Self {
+        Self::builder(seventv_client)
+            .data_source(data_source)
+            .build()
+            .await
+    }
+
+    pub fn builder<'a>(seventv_client: Arc<SevenTVClient>) -> MessageProcessorSetupBuilder<'a> {
+        MessageProcessorSetupBuilder {
+            data_source: None,
+            scoring: ScoringConfig::default(),
+            builtin_metrics: metrics::BUILTIN_METRICS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            custom_metrics: Vec::new(),
+            metric_workers: metrics::default_workers(),
+            builtin_metadata: metadata::BUILTIN_METADATA
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            custom_metadata: Vec::new(),
+            seventv_client,
+        }
+    }
 
     pub async fn start(mut self) -> MessageProcessorRunning {
         let (performance_sender, performance_receiver) = oneshot::channel();
@@ -70,8 +243,7 @@ impl MessageProcessorSetup {
         debug!("Constructing MessageProcessorRunning");
         MessageProcessorRunning {
             joins,
-            metric_sender: self.metric_sender,
-            metadata_sender: self.metadata_sender,
+            fan_out: self.fan_out,
             performance_receiver,
             sequence_number: AtomicU32::new(0)
         }
@@ -81,52 +253,102 @@ impl MessageProcessorSetup {
 /// A running message processor that can process messages
 pub struct MessageProcessorRunning {
     joins: JoinSet<()>,
-    metric_sender: tokio::sync::broadcast::Sender<(Message, u32)>,
-    metadata_sender: tokio::sync::broadcast::Sender<(Message, u32)>,
+    fan_out: FanOut<(Arc<Message>, u32)>,
     performance_receiver: tokio::sync::oneshot::Receiver<HashMap<String, UserChatPerformance>>,
     sequence_number: AtomicU32,
 }
 
 impl MessageProcessorRunning {
-    pub async fn process_message(&self, message: Message) {
+    /// Sends a message to every metric and metadata. The message is
+    /// shared between them rather than copied.
+    ///
+    /// Waits if any of them has fallen behind. Errors if any of them has
+    /// stopped early, as its results would be incomplete.
+    pub async fn process_message(&self, message: Message) -> Result<(), FanOutError> {
         let sequence_number = self
             .sequence_number
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        for sender in [&self.metric_sender, &self.metadata_sender] {
-            sender.send((message.clone(), sequence_number)).unwrap();
-        }
+        self.fan_out
+            .send((Arc::new(message), sequence_number))
+            .await
     }
 
-    pub async fn finish(self) -> HashMap<String, UserChatPerformance> {
-        // These senders need to be dropped before `metadata_processor_task`
+    /// Waits for every message to be processed, returning the performances
+    /// sorted by user id
+    pub async fn finish(self) -> Vec<UserChatPerformance> {
+        // The fan-out needs to be closed before `metadata_processor_task`
         // and `metric_processor_task` will exit.
-        drop(self.metric_sender);
-        drop(self.metadata_sender);
+        self.fan_out.close();
 
         self.joins.join_all().await;
 
-        self.performance_receiver.await.expect("Received perfomances")
+        let mut performances: Vec<UserChatPerformance> = self
+            .performance_receiver
+            .await
+            .expect("Received perfomances")
+            .into_values()
+            .collect();
+        performances.sort_by(|a, b| a.id.cmp(&b.id));
+        performances
     }
 }
 
+/// Sequence number given to the updates a metric flushes in `finish`
+pub const FINISH_SEQUENCE_NO: u32 = u32::MAX;
+
+/// Collects the metric and metadata updates into user performances.
+///
+/// Updates are tagged with the sequence number of the message they came
+/// from. Metric updates are summed, and each metric's updates arrive in
+/// sequence order, so the sums don't depend on how the metrics were
+/// scheduled. Metadata is resolved by the highest sequence number rather
+/// than by whichever update happened to arrive last.
 pub async fn performance_processor(
     metric_defaults: HashMap<String, f32>,
-    mut metric_receiver: mpsc::Receiver<MetricUpdate>,
+    mut metric_receiver: mpsc::Receiver<(MetricUpdate, u32)>,
     metadata_defaults: HashMap<String, MetadataTypes>,
-    mut metadata_receiver: mpsc::Receiver<MetadataUpdate>,
+    mut metadata_receiver: mpsc::Receiver<(MetadataUpdate, u32)>,
     performance_sender: oneshot::Sender<HashMap<String, UserChatPerformance>>,
 ) {
     let mut user_performances: HashMap<String, UserChatPerformance> = HashMap::new();
+    let mut last_metric_sequence: HashMap<String, u32> = HashMap::new();
+    let mut metadata_sequence: HashMap<String, HashMap<String, u32>> = HashMap::new();
     loop {
         tokio::select! {
-            Some(metric_update) = metric_receiver.recv() => {
+            Some((metric_update, sequence_no)) = metric_receiver.recv() => {
+                let last_sequence_no = last_metric_sequence
+                    .entry(metric_update.metric_name.clone())
+                    .or_insert(sequence_no);
+                if sequence_no < *last_sequence_no {
+                    warn!(
+                        "Metric {} sent update {} after {}, totals may not be reproducible",
+                        metric_update.metric_name, sequence_no, last_sequence_no
+                    );
+                }
+                *last_sequence_no = sequence_no;
+
                 metric_update.updates.iter().for_each(|(user_id, met_value)| {
                     let user_chat_performance = get_performance_or_default(&mut user_performances, user_id, &metric_defaults, &metadata_defaults);
                     user_chat_performance.metrics.entry(metric_update.metric_name.clone()).and_modify(|metric_value| *metric_value += met_value);
                 });
             }
-            Some(metadata_update) = metadata_receiver.recv() => {
+            Some((metadata_update, sequence_no)) = metadata_receiver.recv() => {
+                let latest_sequence = metadata_sequence
+                    .entry(metadata_update.metadata_name.clone())
+                    .or_default();
                 metadata_update.updates.iter().for_each(|(user_id, met_value)| {
+                    let latest_sequence_no = latest_sequence
+                        .entry(user_id.clone())
+                        .or_insert(sequence_no);
+                    if sequence_no < *latest_sequence_no {
+                        debug!(
+                            "Ignoring stale {} update {} for user {}",
+                            metadata_update.metadata_name, sequence_no, user_id
+                        );
+                        return;
+                    }
+                    *latest_sequence_no = sequence_no;
+
                     let user_chat_performance = get_performance_or_default(&mut user_performances, user_id, &metric_defaults, &metadata_defaults);
                     match metadata_update.metadata_name.as_str() {
                         "basic_info" => {
@@ -170,3 +392,149 @@ fn get_performance_or_default<'a>(
             metadata: metadatas.clone(),
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+    use twitch_utils::twitchtypes::Comment;
+
+    use super::*;
+
+    /// A chat log where users bits, gift subs, spam a copypasta and change
+    /// their display name part way through
+    fn chat_log() -> Vec<Message> {
+        (0..2000)
+            .map(|i| {
+                let user = i % 37;
+                let body = match i % 5 {
+                    0 => format!("user{} gifted a Tier 1 Sub to someone!", user),
+                    1 => "this is a copypasta that keeps getting pasted".to_string(),
+                    2 => format!(
+                        "user{} is gifting {} Tier 1 Subs to vedal987's community!",
+                        user,
+                        i % 4 + 1
+                    ),
+                    _ => format!("message {} from user {}", i, user),
+                };
+                let comment: Comment = serde_json::from_value(json!({
+                    "_id": i.to_string(),
+                    "message": {
+                        "body": body,
+                        "bits_spent": if i % 7 == 0 { 100 } else { 0 },
+                        "fragments": [{ "text": body, "emoticon": null }],
+                        "user_badges": null,
+                    },
+                    "commenter": {
+                        "display_name": format!("user{}_{}", user, i / 1000),
+                        "_id": format!("id{}", user),
+                        "logo": "",
+                    },
+                }))
+                .unwrap();
+                Message::from(comment)
+            })
+            .collect()
+    }
+
+    async fn run(metric_workers: usize) -> Vec<UserChatPerformance> {
+        let processor = MessageProcessorSetup::builder(Arc::new(SevenTVClient::default()))
+            .metric_workers(metric_workers)
+            .build()
+            .await
+            .start()
+            .await;
+        for message in chat_log() {
+            processor.process_message(message).await.unwrap();
+        }
+        processor.finish().await
+    }
+
+    /// Everything about a performance, with metrics compared bit for bit
+    fn summary(performance: &UserChatPerformance) -> String {
+        let metrics: BTreeMap<_, _> = performance
+            .metrics
+            .iter()
+            .map(|(name, value)| (name, value.to_bits()))
+            .collect();
+        let metadata: BTreeMap<_, _> = performance
+            .metadata
+            .iter()
+            .map(|(name, value)| (name, format!("{:?}", value)))
+            .collect();
+        format!(
+            "{} {} {:?} {:?}",
+            performance.id, performance.username, metrics, metadata
+        )
+    }
+
+    /// Names of the metrics the performances of a run over the chat log have
+    async fn metric_names(builder: MessageProcessorSetupBuilder<'_>) -> Vec<String> {
+        let processor = builder.build().await.start().await;
+        for message in chat_log() {
+            processor.process_message(message).await.unwrap();
+        }
+        let mut names: Vec<String> = processor.finish().await[0]
+            .metrics
+            .keys()
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn metrics_can_be_disabled_and_enabled_by_name() {
+        let builder = || MessageProcessorSetup::builder(Arc::new(SevenTVClient::default()));
+
+        let disabled = metric_names(builder().disable_metric("text")).await;
+        assert!(!disabled.contains(&"text".to_string()));
+        assert_eq!(disabled.len(), metrics::BUILTIN_METRICS.len() - 1);
+
+        let enabled = metric_names(builder().disable_metric("text").enable_metric("text")).await;
+        assert_eq!(enabled.len(), metrics::BUILTIN_METRICS.len());
+    }
+
+    #[tokio::test]
+    async fn unknown_metric_names_are_ignored() {
+        let builder = MessageProcessorSetup::builder(Arc::new(SevenTVClient::default()))
+            .enable_metric("unknown")
+            .disable_metric("unknown");
+
+        let mut builtins = metrics::BUILTIN_METRICS.map(String::from).to_vec();
+        builtins.sort();
+        assert_eq!(metric_names(builder).await, builtins);
+    }
+
+    #[tokio::test]
+    async fn sharded_metrics_match_a_sequential_run() {
+        let sequential: Vec<String> = run(1).await.iter().map(summary).collect();
+        let sharded: Vec<String> = run(4).await.iter().map(summary).collect();
+
+        assert_eq!(sequential.len(), 37);
+        assert_eq!(sequential, sharded);
+    }
+
+    #[tokio::test]
+    async fn repeated_runs_are_identical() {
+        let first = run(4).await;
+        let second = run(4).await;
+
+        let ids: Vec<&str> = first
+            .iter()
+            .map(|performance| performance.id.as_str())
+            .collect();
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(ids, sorted);
+        // The name from the user's latest message wins
+        assert!(first
+            .iter()
+            .all(|performance| performance.username.ends_with("_1")));
+        assert_eq!(
+            first.iter().map(summary).collect::<Vec<_>>(),
+            second.iter().map(summary).collect::<Vec<_>>()
+        );
+    }
+}
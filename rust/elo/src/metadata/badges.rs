@@ -11,7 +11,7 @@ use crate::_types::{
     leaderboardtypes::BadgeInformation,
 };
 use crate::metadata::metadatatrait::AbstractMetadata;
-use twitch_utils::TwitchAPIWrapper;
+use twitch_utils::datasource::ChatDataSource;
 
 lazy_static! {
     static ref DISCORD_ROLE_MAPPING: HashMap<String, DiscordMapping> = HashMap::from([
@@ -63,23 +63,22 @@ pub struct Badges {
 }
 
 impl Badges {
-    pub async fn new(twitch: &TwitchAPIWrapper) -> Self {
-        let badges = twitch
+    pub async fn new(data_source: &dyn ChatDataSource) -> Result<Self, Box<dyn std::error::Error>> {
+        let badges = data_source
             .get_badges(VED_CH_ID.to_string())
-            .await
-            .unwrap()
+            .await?
             .into_iter()
             .map(|(set_id, badge_set)| {
                 (
                     set_id.clone(),
                     badge_set
                         .into_iter()
-                        .map(|(badge_id, badge)| {
+                        .map(|(badge_id, image_url)| {
                             (
                                 badge_id,
                                 BadgeInformation {
                                     description: set_id.clone(),
-                                    image_url: badge.image_url_4x,
+                                    image_url,
                                 },
                             )
                         })
@@ -87,12 +86,12 @@ impl Badges {
                 )
             })
             .collect();
-        Self { badges }
+        Ok(Self { badges })
     }
 
-    fn get_metadata_twitch(&self, comment: Comment) -> MetadataUpdate {
+    fn get_metadata_twitch(&self, comment: &Comment) -> MetadataUpdate {
         let mut metadata: Vec<BadgeInformation> = vec![];
-        let user_badges = if let Some(user_badges) = comment.message.user_badges {
+        let user_badges = if let Some(user_badges) = &comment.message.user_badges {
             user_badges
         } else {
             let mut out: HashMap<String, MetadataTypes> = HashMap::new();
@@ -131,7 +130,7 @@ impl Badges {
         }
     }
 
-    fn get_metadata_discord(&self, msg: DiscordMessage) -> MetadataUpdate {
+    fn get_metadata_discord(&self, msg: &DiscordMessage) -> MetadataUpdate {
         let metadata = msg
             .author
             .roles
@@ -148,7 +147,7 @@ impl Badges {
 
         MetadataUpdate {
             metadata_name: self.get_name(),
-            updates: HashMap::from([(msg.author.id, MetadataTypes::BadgeList(metadata))]),
+            updates: HashMap::from([(msg.author.id.clone(), MetadataTypes::BadgeList(metadata))]),
         }
     }
 }
@@ -162,7 +161,7 @@ impl AbstractMetadata for Badges {
         MetadataTypes::BadgeList(vec![])
     }
 
-    fn get_metadata(&self, message: Message, _sequence_no: u32) -> MetadataUpdate {
+    fn get_metadata(&self, message: &Message, _sequence_no: u32) -> MetadataUpdate {
         match message {
             Message::Twitch(comment) => self.get_metadata_twitch(comment),
             Message::Discord(msg) => self.get_metadata_discord(msg),
@@ -21,14 +21,17 @@ impl AbstractMetadata for BasicInfo {
         MetadataTypes::BasicInfo("".to_string(), "".to_string())
     }
 
-    fn get_metadata(&self, message: Message, _sequence_no: u32) -> MetadataUpdate {
+    fn get_metadata(&self, message: &Message, _sequence_no: u32) -> MetadataUpdate {
         match message {
             Message::Twitch(comment) => self.process_twitch(comment),
             Message::Discord(msg) => MetadataUpdate {
                 metadata_name: self.get_name(),
                 updates: HashMap::from([(
-                    msg.author.id,
-                    MetadataTypes::BasicInfo(msg.author.nickname, msg.author.avatar_url),
+                    msg.author.id.clone(),
+                    MetadataTypes::BasicInfo(
+                        msg.author.nickname.clone(),
+                        msg.author.avatar_url.clone(),
+                    ),
                 )]),
             },
             _ => MetadataUpdate::default(),
@@ -43,11 +46,11 @@ impl BasicInfo {
         }
     }
 
-    fn process_twitch(&self, comment: twitch_utils::twitchtypes::Comment) -> MetadataUpdate {
+    fn process_twitch(&self, comment: &twitch_utils::twitchtypes::Comment) -> MetadataUpdate {
         MetadataUpdate {
             metadata_name: self.get_name(),
             updates: self.seventv_client
-                .get_emotes_in_comment(&comment)
+                .get_emotes_in_comment(comment)
                 .into_iter()
                 .map(|emote| {
                     (
@@ -56,10 +59,10 @@ impl BasicInfo {
                     )
                 })
                 .chain(std::iter::once((
-                    comment.commenter._id,
+                    comment.commenter._id.clone(),
                     MetadataTypes::BasicInfo(
-                        comment.commenter.display_name,
-                        comment.commenter.logo,
+                        comment.commenter.display_name.clone(),
+                        comment.commenter.logo.clone(),
                     ),
                 )))
                 .collect(),
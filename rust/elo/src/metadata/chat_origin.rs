@@ -21,14 +21,14 @@ impl AbstractMetadata for ChatOrigin {
         MetadataTypes::ChatOrigin(MessageTag::None)
     }
 
-    fn get_metadata(&self, message: Message, _sequence_no: u32) -> MetadataUpdate {
+    fn get_metadata(&self, message: &Message, _sequence_no: u32) -> MetadataUpdate {
         MetadataUpdate {
             metadata_name: self.get_name(),
-            updates: match &message {
-                Message::Twitch(comment) => self.process_twitch(comment, &message),
+            updates: match message {
+                Message::Twitch(comment) => self.process_twitch(comment, message),
                 Message::Discord(msg) => HashMap::from([(
                     msg.author.id.to_string(),
-                    MetadataTypes::ChatOrigin(MessageTag::from(&message)),
+                    MetadataTypes::ChatOrigin(MessageTag::from(message)),
                 )]),
                 _ => HashMap::new(),
             },
@@ -1,7 +1,7 @@
 //! Represents an abstract metadata
 use crate::_types::clptypes::{Message, MetadataTypes, MetadataUpdate};
 
-pub trait AbstractMetadata: Sized {
+pub trait AbstractMetadata {
     /*
     Structs that implement this trait represent a piece of metadata
 
@@ -16,5 +16,5 @@ pub trait AbstractMetadata: Sized {
     fn get_default_value(&self) -> MetadataTypes;
 
     /// Get information about a user from a chat message
-    fn get_metadata(&self, message: Message, sequence_no: u32) -> MetadataUpdate;
+    fn get_metadata(&self, message: &Message, sequence_no: u32) -> MetadataUpdate;
 }
@@ -4,128 +4,286 @@ pub mod chat_origin;
 pub mod metadatatrait;
 pub mod special_role;
 
-use futures::join;
+use futures::future::join_all;
 use log::debug;
 use log::warn;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::broadcast;
 use tokio::sync::mpsc;
 use twitch_utils::seventvclient::SevenTVClient;
 
 use crate::_types::clptypes::Message;
 use crate::_types::clptypes::MetadataTypes;
 use crate::_types::clptypes::MetadataUpdate;
+use crate::fanout::FanOut;
 use crate::metadata::metadatatrait::AbstractMetadata;
-use twitch_utils::TwitchAPIWrapper;
+use twitch_utils::datasource::ChatDataSource;
 
-struct WithReceiver<M: AbstractMetadata> {
-    pub metadata: M,
-    pub receiver: broadcast::Receiver<(Message, u32)>,
-    pub sender: mpsc::Sender<MetadataUpdate>,
+/// Names of the metadata that ship with this crate
+pub const BUILTIN_METADATA: [&str; 4] = ["basic_info", "badges", "special_role", "chat_origin"];
+
+/// Constructs a built-in piece of metadata from its name.
+///
+/// Returns `None` if there is no such metadata, or if it needs Twitch
+/// data that no data source was given for or that couldn't be fetched.
+pub async fn builtin_metadata(
+    name: &str,
+    data_source: Option<&dyn ChatDataSource>,
+    seventv_client: Arc<SevenTVClient>,
+) -> Option<Box<dyn AbstractMetadata + Send>> {
+    let metadata: Box<dyn AbstractMetadata + Send> = match name {
+        "basic_info" => Box::new(basic_info::BasicInfo::new(seventv_client)),
+        "badges" => match data_source {
+            Some(data_source) => match badges::Badges::new(data_source).await {
+                Ok(badges) => Box::new(badges),
+                Err(e) => {
+                    warn!(
+                        "Could not get the chat badges, skipping the badges metadata: {}",
+                        e
+                    );
+                    return None;
+                }
+            },
+            None => {
+                warn!("The badges metadata needs a data source, skipping");
+                return None;
+            }
+        },
+        "special_role" => Box::new(special_role::SpecialRole::new()),
+        "chat_origin" => Box::new(chat_origin::ChatOrigin::new(seventv_client)),
+        _ => return None,
+    };
+    Some(metadata)
 }
 
-impl<M: AbstractMetadata> WithReceiver<M> {
-    fn new(
-        metadata: M,
-        receiver: &broadcast::Receiver<(Message, u32)>,
-        sender: &mpsc::Sender<MetadataUpdate>,
+/// The set of metadata that will be collected from the chat messages.
+///
+/// Metadata is identified by `AbstractMetadata::get_name`, so
+/// registering metadata with the same name as an existing one replaces it.
+#[derive(Default)]
+pub struct MetadataRegistry {
+    metadata: Vec<Box<dyn AbstractMetadata + Send>>,
+}
+
+impl MetadataRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry with all of the built-in metadata
+    pub async fn with_builtins(
+        data_source: Option<&dyn ChatDataSource>,
+        seventv_client: Arc<SevenTVClient>,
     ) -> Self {
-        Self {
-            metadata,
-            receiver: receiver.resubscribe(),
-            sender: sender.clone(),
+        let mut registry = Self::new();
+        for name in BUILTIN_METADATA {
+            if let Some(metadata) =
+                builtin_metadata(name, data_source, seventv_client.clone()).await
+            {
+                registry.register(metadata);
+            }
+        }
+        registry
+    }
+
+    /// Adds a piece of metadata to the registry
+    pub fn register(&mut self, metadata: Box<dyn AbstractMetadata + Send>) {
+        let name = metadata.get_name();
+        if let Some(existing) = self.metadata.iter_mut().find(|m| m.get_name() == name) {
+            debug!("Replacing already registered metadata {}", name);
+            *existing = metadata;
+        } else {
+            self.metadata.push(metadata);
         }
     }
 
-    fn get_name(&self) -> String {
-        self.metadata.get_name()
+    /// Removes a piece of metadata from the registry, returning it if it was registered
+    pub fn unregister(&mut self, name: &str) -> Option<Box<dyn AbstractMetadata + Send>> {
+        let index = self.metadata.iter().position(|m| m.get_name() == name)?;
+        Some(self.metadata.remove(index))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.metadata.iter().any(|m| m.get_name() == name)
     }
 
-    fn get_metadata(&mut self, message: Message, sequence_no: u32) -> MetadataUpdate {
-        self.metadata.get_metadata(message, sequence_no)
+    /// Names and default values of all the registered metadata
+    pub fn defaults(&self) -> HashMap<String, MetadataTypes> {
+        self.metadata
+            .iter()
+            .map(|m| (m.get_name(), m.get_default_value()))
+            .collect()
+    }
+}
+
+struct WithReceiver {
+    pub metadata: Box<dyn AbstractMetadata + Send>,
+    pub receiver: mpsc::Receiver<(Arc<Message>, u32)>,
+    pub sender: mpsc::Sender<(MetadataUpdate, u32)>,
+}
+
+impl WithReceiver {
+    fn new(
+        metadata: Box<dyn AbstractMetadata + Send>,
+        fan_out: &mut FanOut<(Arc<Message>, u32)>,
+        sender: &mpsc::Sender<(MetadataUpdate, u32)>,
+    ) -> Self {
+        Self {
+            receiver: fan_out.subscribe(metadata.get_name()),
+            metadata,
+            sender: sender.clone(),
+        }
     }
 
-    fn get_default_value(&self) -> MetadataTypes {
-        self.metadata.get_default_value()
+    fn get_metadata(&mut self, message: &Message, sequence_no: u32) -> (MetadataUpdate, u32) {
+        (
+            self.metadata.get_metadata(message, sequence_no),
+            sequence_no,
+        )
     }
 }
 
 pub struct MetadataProcessor {
     pub defaults: HashMap<String, MetadataTypes>,
-    basic_info: WithReceiver<basic_info::BasicInfo>,
-    badges: WithReceiver<badges::Badges>,
-    special_role: WithReceiver<special_role::SpecialRole>,
-    chat_origin: WithReceiver<chat_origin::ChatOrigin>,
+    metadata: Vec<WithReceiver>,
 }
 
 impl MetadataProcessor {
-    pub async fn new(
-        twitch: &TwitchAPIWrapper,
-        seventv_client: Arc<SevenTVClient>,
-        broadcast_receiver: broadcast::Receiver<(Message, u32)>,
-        mpsc_sender: mpsc::Sender<MetadataUpdate>,
+    /// Create a new MetadataProcessor running every metadata in the registry
+    pub fn new(
+        registry: MetadataRegistry,
+        fan_out: &mut FanOut<(Arc<Message>, u32)>,
+        mpsc_sender: mpsc::Sender<(MetadataUpdate, u32)>,
     ) -> Self {
-        let mut defaults: HashMap<String, MetadataTypes> = HashMap::new();
+        let defaults = registry.defaults();
 
-        // Initialize the metadata
-        let basic_info = WithReceiver::new(basic_info::BasicInfo::new(seventv_client.clone()), &broadcast_receiver, &mpsc_sender);
-        let badges = WithReceiver::new(badges::Badges::new(twitch).await, &broadcast_receiver, &mpsc_sender);
-        let special_role = WithReceiver::new(special_role::SpecialRole::new(), &broadcast_receiver, &mpsc_sender);
-        let chat_origin = WithReceiver::new(chat_origin::ChatOrigin::new(seventv_client), &broadcast_receiver, &mpsc_sender);
+        let metadata = registry
+            .metadata
+            .into_iter()
+            .map(|metadata| WithReceiver::new(metadata, fan_out, &mpsc_sender))
+            .collect();
 
-        // Add names and default values to the metadata
-        defaults.insert(basic_info.get_name(), basic_info.get_default_value());
-        defaults.insert(badges.get_name(), badges.get_default_value());
-        defaults.insert(special_role.get_name(), special_role.get_default_value());
-        defaults.insert(chat_origin.get_name(), chat_origin.get_default_value());
-
-        Self {
-            defaults,
-            basic_info,
-            badges,
-            special_role,
-            chat_origin,
-        }
+        Self { defaults, metadata }
     }
 
     pub async fn run(&mut self) {
-        join!(
-            calc_metadata(&mut self.basic_info),
-            calc_metadata(&mut self.badges),
-            calc_metadata(&mut self.special_role),
-            calc_metadata(&mut self.chat_origin),
-        );
+        join_all(self.metadata.iter_mut().map(calc_metadata)).await;
         debug!("All metadata finished");
     }
 }
 
-async fn calc_metadata<M: AbstractMetadata + Send + Sync + 'static>(
-    metadata: &mut WithReceiver<M>,
-) {
+async fn calc_metadata(metadata: &mut WithReceiver) {
     /*
-    Find metadata based on chat messages sent by a tokio broadcast channel
+    Find metadata based on chat messages sent by the fan-out
     */
-    while let Ok((message, sequence_no)) = metadata.receiver.recv().await {
-        let metadata_update = (*metadata).get_metadata(message, sequence_no);
+    while let Some((message, sequence_no)) = metadata.receiver.recv().await {
+        let metadata_update = metadata.get_metadata(&message, sequence_no);
         if let Err(e) = metadata.sender.send(metadata_update).await {
             warn!("Failed to send metadata result {}", e)
         };
     }
 }
 
-/// Get the default values for the metrics and set up the channels
-pub async fn setup_metadata_and_channels(
-    twitch: &TwitchAPIWrapper,
-    seventv_client: Arc<SevenTVClient>,
-) -> (
-    MetadataProcessor,
-    broadcast::Sender<(Message, u32)>,
-    mpsc::Receiver<MetadataUpdate>,
-) {
-    let (broadcast_sender, broadcast_receiver) = broadcast::channel(100000);
+/// Set up the channels for the metadata in the registry, subscribing
+/// each of them to the fan-out
+pub fn setup_metadata_and_channels(
+    registry: MetadataRegistry,
+    fan_out: &mut FanOut<(Arc<Message>, u32)>,
+) -> (MetadataProcessor, mpsc::Receiver<(MetadataUpdate, u32)>) {
     let (mpsc_sender, mpsc_receiver) = mpsc::channel(100000);
-    let metadata_processor =
-        MetadataProcessor::new(twitch, seventv_client, broadcast_receiver, mpsc_sender).await;
-    (metadata_processor, broadcast_sender, mpsc_receiver)
+    let metadata_processor = MetadataProcessor::new(registry, fan_out, mpsc_sender);
+    (metadata_processor, mpsc_receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use twitch_utils::datasource::{BadgeSets, DataSnapshot, SnapshotDataSource, VodTimes};
+    use twitch_utils::twitchtypes::SevenTVEmote;
+
+    use super::*;
+
+    /// A data source whose Twitch API calls all fail
+    struct Offline;
+
+    #[async_trait]
+    impl ChatDataSource for Offline {
+        async fn get_latest_vod_ids(&self, _ch_id: String, _num: usize) -> Vec<String> {
+            Vec::new()
+        }
+
+        async fn get_vod_times(
+            &self,
+            _vod_id: String,
+        ) -> Result<VodTimes, Box<dyn std::error::Error>> {
+            Err("offline".into())
+        }
+
+        async fn get_badges(
+            &self,
+            _ch_id: String,
+        ) -> Result<BadgeSets, Box<dyn std::error::Error>> {
+            Err("offline".into())
+        }
+
+        async fn get_seventv_emotes(&self) -> Vec<SevenTVEmote> {
+            Vec::new()
+        }
+    }
+
+    async fn names(data_source: Option<&dyn ChatDataSource>) -> Vec<String> {
+        let registry =
+            MetadataRegistry::with_builtins(data_source, Arc::new(SevenTVClient::default())).await;
+        let mut names: Vec<String> = registry.defaults().into_keys().collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn builtins_are_registered_with_a_data_source() {
+        let snapshot = SnapshotDataSource::new(DataSnapshot::default());
+
+        assert_eq!(
+            names(Some(&snapshot)).await,
+            ["badges", "basic_info", "chat_origin", "special_role"]
+        );
+    }
+
+    #[tokio::test]
+    async fn badges_are_skipped_without_a_data_source() {
+        assert_eq!(
+            names(None).await,
+            ["basic_info", "chat_origin", "special_role"]
+        );
+    }
+
+    #[tokio::test]
+    async fn badges_are_skipped_if_they_cannot_be_fetched() {
+        assert_eq!(
+            names(Some(&Offline)).await,
+            ["basic_info", "chat_origin", "special_role"]
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_names_are_not_builtins() {
+        let client = Arc::new(SevenTVClient::default());
+
+        assert!(builtin_metadata("unknown", Some(&Offline), client)
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn registering_metadata_with_a_taken_name_replaces_it() {
+        let mut registry = MetadataRegistry::new();
+        registry.register(Box::new(special_role::SpecialRole::new()));
+        registry.register(Box::new(special_role::SpecialRole::new()));
+
+        assert_eq!(registry.defaults().len(), 1);
+        assert!(registry.unregister("special_role").is_some());
+        assert!(!registry.contains("special_role"));
+        assert!(registry.unregister("special_role").is_none());
+    }
 }
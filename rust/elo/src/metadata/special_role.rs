@@ -17,9 +17,9 @@ impl SpecialRole {
         Self
     }
 
-    fn get_metadata_twitch(&self, comment: Comment) -> MetadataUpdate {
+    fn get_metadata_twitch(&self, comment: &Comment) -> MetadataUpdate {
         let mut metadata: HashMap<String, MetadataTypes> = HashMap::new();
-        let user_badges = comment.message.user_badges;
+        let user_badges = &comment.message.user_badges;
         if user_badges.is_none() {
             metadata.insert(comment.commenter._id.clone(), MetadataTypes::Bool(false));
             return MetadataUpdate {
@@ -27,7 +27,7 @@ impl SpecialRole {
                 updates: metadata,
             };
         }
-        let user_badges = user_badges.unwrap();
+        let user_badges = user_badges.as_ref().unwrap();
         for badge in user_badges {
             if SPECIAL_ROLES_TWITCH.contains(&badge._id.as_str()) {
                 metadata.insert(comment.commenter._id.clone(), MetadataTypes::Bool(true));
@@ -44,7 +44,7 @@ impl SpecialRole {
         }
     }
 
-    fn get_metadata_discord(&self, msg: DiscordMessage) -> MetadataUpdate {
+    fn get_metadata_discord(&self, msg: &DiscordMessage) -> MetadataUpdate {
         MetadataUpdate {
             metadata_name: self.get_name(),
             updates: msg
@@ -52,7 +52,7 @@ impl SpecialRole {
                 .roles
                 .iter()
                 .rfind(|role| SPECIAL_ROLES_DISCORD.contains(&role.name.as_str()))
-                .map(|_| HashMap::from([(msg.author.id, MetadataTypes::Bool(true))]))
+                .map(|_| HashMap::from([(msg.author.id.clone(), MetadataTypes::Bool(true))]))
                 .unwrap_or_default(),
         }
     }
@@ -67,7 +67,7 @@ impl AbstractMetadata for SpecialRole {
         MetadataTypes::Bool(false)
     }
 
-    fn get_metadata(&self, message: Message, _sequence_no: u32) -> MetadataUpdate {
+    fn get_metadata(&self, message: &Message, _sequence_no: u32) -> MetadataUpdate {
         match message {
             Message::Twitch(comment) => self.get_metadata_twitch(comment),
             Message::Discord(msg) => self.get_metadata_discord(msg),
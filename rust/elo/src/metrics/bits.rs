@@ -1,14 +1,15 @@
 use crate::_types::clptypes::{Message, MetricUpdate};
 use crate::metrics::metrictrait::AbstractMetric;
+use crate::scoring::MetricScoring;
 
-const WEIGHT_BITS: f32 = 0.1;
-
-#[derive(Default, Debug)]
-pub struct Bits;
+#[derive(Debug)]
+pub struct Bits {
+    scoring: MetricScoring,
+}
 
 impl Bits {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(scoring: MetricScoring) -> Self {
+        Self { scoring }
     }
 }
 
@@ -17,14 +18,18 @@ impl AbstractMetric for Bits {
         true
     }
 
+    fn fork(&self) -> Option<Box<dyn AbstractMetric + Send>> {
+        Some(Box::new(Self::new(self.scoring.clone())))
+    }
+
     fn get_name(&self) -> String {
         String::from("bits")
     }
 
-    fn get_metric(&mut self, message: Message, _sequence_no: u32) -> MetricUpdate {
+    fn get_metric(&mut self, message: &Message, _sequence_no: u32) -> MetricUpdate {
         match message {
             Message::Twitch(comment) => {
-                let score = comment.message.bits_spent as f32 * WEIGHT_BITS;
+                let score = self.scoring.score(comment.message.bits_spent as f32);
                 self.twitch_comment_shortcut(comment, score)
             }
             _ => MetricUpdate::empty_with_name(self.get_name()),
@@ -2,21 +2,20 @@ use log::debug;
 
 use crate::_types::clptypes::{Message, MetricUpdate};
 use crate::metrics::metrictrait::AbstractMetric;
+use crate::scoring::CopypastaScoring;
 use twitch_utils::twitchtypes::Comment;
 
-const WEIGHT_COPYPASTA: f32 = 0.3;
-const CHAIN_GRACE: u32 = 10;
-const MATCHING_THRESHOLD: f32 = 0.6;
-
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct CopypastaLeader {
     history: Vec<(u32, String, String, u32)>,
+    scoring: CopypastaScoring,
 }
 
 impl CopypastaLeader {
-    pub fn new() -> Self {
+    pub fn new(scoring: CopypastaScoring) -> Self {
         Self {
             history: Vec::new(),
+            scoring,
         }
     }
 }
@@ -24,7 +23,7 @@ impl CopypastaLeader {
 impl CopypastaLeader {
     fn get_metric_for_twitch_message(
         &mut self,
-        comment: Comment,
+        comment: &Comment,
         sequence_no: u32,
     ) -> MetricUpdate {
         let text = comment
@@ -64,7 +63,7 @@ impl CopypastaLeader {
             .unwrap();
 
         // If the best match is above the threshold, update the list
-        let parent_sequence = if best_match_score > MATCHING_THRESHOLD {
+        let parent_sequence = if best_match_score > self.scoring.matching_threshold {
             best_match.3
         } else {
             sequence_no
@@ -97,14 +96,14 @@ impl CopypastaLeader {
         let result = self
             .history
             .iter()
-            .take_while(|item| (sequence_no - item.0) <= CHAIN_GRACE)
-            .map(|item| (item.2.clone(), (item.0 - item.3) as f32 * WEIGHT_COPYPASTA))
+            .take_while(|item| (sequence_no - item.0) <= self.scoring.chain_grace)
+            .map(|item| (item.2.clone(), self.scoring.score((item.0 - item.3) as f32)))
             .collect();
 
         self.history = self
             .history
             .iter()
-            .skip_while(|item| (sequence_no - item.0) > CHAIN_GRACE)
+            .skip_while(|item| (sequence_no - item.0) > self.scoring.chain_grace)
             .cloned()
             .collect();
 
@@ -124,7 +123,7 @@ impl AbstractMetric for CopypastaLeader {
         String::from("copypasta")
     }
 
-    fn get_metric(&mut self, message: Message, sequence_no: u32) -> MetricUpdate {
+    fn get_metric(&mut self, message: &Message, sequence_no: u32) -> MetricUpdate {
         match message {
             Message::Twitch(comment) => self.get_metric_for_twitch_message(comment, sequence_no),
             _ => MetricUpdate::empty_with_name(self.get_name()), // TODO: Discord copypasta leader
@@ -137,7 +136,7 @@ impl AbstractMetric for CopypastaLeader {
             updates: self
                 .history
                 .iter()
-                .map(|item| (item.2.clone(), (item.0 - item.3) as f32 * WEIGHT_COPYPASTA))
+                .map(|item| (item.2.clone(), self.scoring.score((item.0 - item.3) as f32)))
                 .collect(),
         }
     }
@@ -176,7 +175,7 @@ fn lcs(s1: &str, s2: &str) -> String {
             },
             None => match s1_prev_pos.peek() {
                 Some(_) => {
-                    if max.as_ref().map_or(true, |s| s.len() < current.len()) {
+                    if max.as_ref().is_none_or(|s| s.len() < current.len()) {
                         max = Some(current.clone());
                     }
                     current.clear();
@@ -2,20 +2,21 @@
 use std::sync::Arc;
 
 use crate::_types::clptypes::{Message, MetricUpdate};
+use crate::scoring::MetricScoring;
 use twitch_utils::seventvclient::SevenTVClient;
 
 use super::metrictrait::AbstractMetric;
 
-const WEIGHT_EMOTES: f32 = 0.02;
-
 pub struct Emote {
     seventv_client: Arc<SevenTVClient>,
+    scoring: MetricScoring,
 }
 
 impl Emote {
-    pub fn new(seventv_client: Arc<SevenTVClient>) -> Self {
+    pub fn new(seventv_client: Arc<SevenTVClient>, scoring: MetricScoring) -> Self {
         Self {
             seventv_client,
+            scoring,
         }
     }
 }
@@ -29,14 +30,12 @@ impl AbstractMetric for Emote {
         String::from("emote")
     }
 
-    fn get_metric(&mut self, message: Message, _sequence_no: u32) -> MetricUpdate {
+    fn get_metric(&mut self, message: &Message, _sequence_no: u32) -> MetricUpdate {
         match message {
             Message::Twitch(comment) => {
                 let score: f32 = self
-                    .seventv_client
-                    .get_emotes_in_comment(&comment)
-                    .len() as f32
-                    * WEIGHT_EMOTES;
+                    .scoring
+                    .score(self.seventv_client.get_emotes_in_comment(comment).len() as f32);
                 self.twitch_comment_shortcut(comment, score)
             }
             _ => MetricUpdate::empty_with_name(self.get_name()), // TODO: discord emotes
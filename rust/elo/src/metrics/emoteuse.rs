@@ -2,20 +2,21 @@
 use std::{collections::HashMap, sync::Arc};
 
 use crate::_types::clptypes::{Message, MetricUpdate};
+use crate::scoring::MetricScoring;
 use twitch_utils::seventvclient::SevenTVClient;
 
 use super::metrictrait::AbstractMetric;
 
-const WEIGHT_EMOTES: f32 = 1.0;
-
 pub struct EmoteUse {
     seventv_client: Arc<SevenTVClient>,
+    scoring: MetricScoring,
 }
 
 impl EmoteUse {
-    pub fn new(seventv_client: Arc<SevenTVClient>) -> Self {
+    pub fn new(seventv_client: Arc<SevenTVClient>, scoring: MetricScoring) -> Self {
         Self {
             seventv_client,
+            scoring,
         }
     }
 }
@@ -29,20 +30,22 @@ impl AbstractMetric for EmoteUse {
         String::from("emote_use")
     }
 
-    fn get_metric(&mut self, message: Message, _sequence_no: u32) -> MetricUpdate {
+    fn get_metric(&mut self, message: &Message, _sequence_no: u32) -> MetricUpdate {
         let update = match message {
-            Message::Twitch(comment) => {
-                MetricUpdate {
-                    metric_name: self.get_name(),
-                    updates: self.seventv_client
-                        .get_emotes_in_comment(&comment)
-                        .iter()
-                        .fold(HashMap::new(), |mut acc, emote| {
-                            *acc.entry(emote.id.clone()).or_insert(0.0) += WEIGHT_EMOTES;
-                            acc
-                        })
-                }
-            }
+            Message::Twitch(comment) => MetricUpdate {
+                metric_name: self.get_name(),
+                updates: self
+                    .seventv_client
+                    .get_emotes_in_comment(comment)
+                    .iter()
+                    .fold(HashMap::new(), |mut acc, emote| {
+                        *acc.entry(emote.id.clone()).or_insert(0.0) += 1.0;
+                        acc
+                    })
+                    .into_iter()
+                    .map(|(emote_id, uses)| (emote_id, self.scoring.score(uses)))
+                    .collect(),
+            },
             _ => MetricUpdate::empty_with_name(self.get_name()), // TODO: discord emotes
         };
         update
@@ -4,31 +4,41 @@ use twitch_utils::twitchtypes::Comment;
 
 /// Defines the trait for a metric
 pub trait AbstractMetric {
-    fn twitch_comment_shortcut(&self, comment: Comment, score: f32) -> MetricUpdate {
+    fn twitch_comment_shortcut(&self, comment: &Comment, score: f32) -> MetricUpdate {
         let mut map: HashMap<String, f32> = HashMap::new();
-        map.insert(comment.commenter._id, score);
+        map.insert(comment.commenter._id.clone(), score);
         MetricUpdate {
             metric_name: self.get_name(),
             updates: map,
         }
     }
 
-    /// Indicates to the executor if this metric can be parallelized
-    #[allow(dead_code)]
+    /// Indicates to the executor if this metric can be parallelized.
+    ///
+    /// A parallelizable metric must not depend on the order or the
+    /// history of the messages it sees, and must implement `fork`.
     fn can_parallelize(&self) -> bool;
 
+    /// Creates a fresh copy of this metric for a parallel worker.
+    ///
+    /// Only called if `can_parallelize` returns true. Metrics that return
+    /// `None` are run sequentially.
+    fn fork(&self) -> Option<Box<dyn AbstractMetric + Send>> {
+        None
+    }
+
     /// Returns the name of the metric
     fn get_name(&self) -> String;
 
     /// Gets the score for a particular message.
     ///
     /// # Parameters
-    /// - `comment`: The comment to process
+    /// - `message`: The message to process
     /// - `sequence_no`: The sequence number of the message
     ///
     /// # Return value
     /// A metric update to be added for the associated user
-    fn get_metric(&mut self, message: Message, sequence_no: u32) -> MetricUpdate;
+    fn get_metric(&mut self, message: &Message, sequence_no: u32) -> MetricUpdate;
 
     /// This method is called when there are no more comments to
     /// process. Useful for metrics that need to flush any remaining
@@ -6,113 +6,212 @@ pub mod metrictrait;
 pub mod subs;
 pub mod text;
 
+use futures::future::join_all;
 use futures::join;
 use log::debug;
 use log::warn;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::broadcast;
 use tokio::sync::mpsc;
 use twitch_utils::seventvclient::SevenTVClient;
 
 use crate::_types::clptypes::Message;
 use crate::_types::clptypes::MetricUpdate;
+use crate::fanout::FanOut;
 use crate::metrics::metrictrait::AbstractMetric;
+use crate::scoring::ScoringConfig;
+use crate::FINISH_SEQUENCE_NO;
 
-struct WithReceiver<M: AbstractMetric> {
-    pub metadata: M,
-    pub receiver: broadcast::Receiver<(Message, u32)>,
-    pub sender: mpsc::Sender<MetricUpdate>,
+/// Names of the metrics that ship with this crate
+pub const BUILTIN_METRICS: [&str; 6] = ["bits", "subs", "text", "copypasta", "emote", "emote_use"];
+
+/// Constructs a built-in metric from its name, scored as configured
+pub fn builtin_metric(
+    name: &str,
+    seventv_client: Arc<SevenTVClient>,
+    scoring: &ScoringConfig,
+) -> Option<Box<dyn AbstractMetric + Send>> {
+    let metric: Box<dyn AbstractMetric + Send> = match name {
+        "bits" => Box::new(bits::Bits::new(scoring.bits.clone())),
+        "subs" => Box::new(subs::Subs::new(scoring.subs.clone())),
+        "text" => Box::new(text::Text::new(scoring.text.clone())),
+        "copypasta" => Box::new(copypastaleader::CopypastaLeader::new(
+            scoring.copypasta.clone(),
+        )),
+        "emote" => Box::new(emote::Emote::new(seventv_client, scoring.emote.clone())),
+        "emote_use" => Box::new(emoteuse::EmoteUse::new(
+            seventv_client,
+            scoring.emote_use.clone(),
+        )),
+        _ => return None,
+    };
+    Some(metric)
+}
+
+/// The set of metrics that will be run over the chat messages.
+///
+/// Metrics are identified by `AbstractMetric::get_name`, so registering
+/// a metric with the same name as an existing one replaces it.
+#[derive(Default)]
+pub struct MetricRegistry {
+    metrics: Vec<Box<dyn AbstractMetric + Send>>,
+}
+
+impl MetricRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry with all of the built-in metrics
+    pub fn with_builtins(seventv_client: Arc<SevenTVClient>, scoring: &ScoringConfig) -> Self {
+        let mut registry = Self::new();
+        for name in BUILTIN_METRICS {
+            registry.register(
+                builtin_metric(name, seventv_client.clone(), scoring)
+                    .expect("built-in metric exists"),
+            );
+        }
+        registry
+    }
+
+    /// Adds a metric to the registry
+    pub fn register(&mut self, metric: Box<dyn AbstractMetric + Send>) {
+        let name = metric.get_name();
+        if let Some(existing) = self.metrics.iter_mut().find(|m| m.get_name() == name) {
+            debug!("Replacing already registered metric {}", name);
+            *existing = metric;
+        } else {
+            self.metrics.push(metric);
+        }
+    }
+
+    /// Removes a metric from the registry, returning it if it was registered
+    pub fn unregister(&mut self, name: &str) -> Option<Box<dyn AbstractMetric + Send>> {
+        let index = self.metrics.iter().position(|m| m.get_name() == name)?;
+        Some(self.metrics.remove(index))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.metrics.iter().any(|m| m.get_name() == name)
+    }
+
+    /// Names of all the registered metrics
+    pub fn names(&self) -> Vec<String> {
+        self.metrics.iter().map(|m| m.get_name()).collect()
+    }
 }
 
-impl<M: AbstractMetric> WithReceiver<M> {
-    fn new(metadata: M, receiver: &broadcast::Receiver<(Message, u32)>, sender: &mpsc::Sender<MetricUpdate>) -> Self {
+struct WithReceiver {
+    pub metric: Box<dyn AbstractMetric + Send>,
+    pub receiver: mpsc::Receiver<(Arc<Message>, u32)>,
+    pub sender: mpsc::Sender<(MetricUpdate, u32)>,
+}
+
+impl WithReceiver {
+    fn new(
+        metric: Box<dyn AbstractMetric + Send>,
+        fan_out: &mut FanOut<(Arc<Message>, u32)>,
+        sender: &mpsc::Sender<(MetricUpdate, u32)>,
+    ) -> Self {
         Self {
-            metadata,
-            receiver: receiver.resubscribe(),
+            receiver: fan_out.subscribe(metric.get_name()),
+            metric,
             sender: sender.clone(),
         }
     }
 
-    fn get_name(&self) -> String {
-        self.metadata.get_name()
+    fn get_metric(&mut self, message: &Message, sequence_no: u32) -> (MetricUpdate, u32) {
+        (self.metric.get_metric(message, sequence_no), sequence_no)
     }
 
-    fn get_metric(&mut self, message: Message, sequence_no: u32) -> MetricUpdate {
-        self.metadata.get_metric(message, sequence_no)
-    } 
-
-    fn finish(&mut self) -> MetricUpdate {
-        self.metadata.finish()
+    fn finish(&mut self) -> (MetricUpdate, u32) {
+        (self.metric.finish(), FINISH_SEQUENCE_NO)
     }
 }
 
+/// Number of messages each parallel metric worker can queue up
+const WORKER_CAPACITY: usize = 1000;
+
+/// Default number of workers for each parallelizable metric
+pub fn default_workers() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get().min(4))
+        .unwrap_or(1)
+}
+
 pub struct MetricProcessor {
     pub defaults: HashMap<String, f32>,
-    bits: WithReceiver<bits::Bits>,
-    subs: WithReceiver<subs::Subs>,
-    text: WithReceiver<text::Text>,
-    copypastaleader: WithReceiver<copypastaleader::CopypastaLeader>,
-    emote: WithReceiver<emote::Emote>,
-    emote_use: WithReceiver<emoteuse::EmoteUse>,
+    metrics: Vec<WithReceiver>,
+    workers: usize,
 }
 
 impl MetricProcessor {
-    /// Create a new MetricProcessor
-    /// get_defaults_and_setup_channels must be called before run
+    /// Create a new MetricProcessor running every metric in the registry
     pub fn new(
-        seventv_client: Arc<SevenTVClient>,
-        broadcast_receiver: broadcast::Receiver<(Message, u32)>,
-        mpsc_sender: mpsc::Sender<MetricUpdate>,
+        registry: MetricRegistry,
+        fan_out: &mut FanOut<(Arc<Message>, u32)>,
+        mpsc_sender: mpsc::Sender<(MetricUpdate, u32)>,
     ) -> Self {
-        let mut defaults: HashMap<String, f32> = HashMap::new();
-
-        let bits = WithReceiver::new(bits::Bits::new(), &broadcast_receiver, &mpsc_sender);
-        let subs = WithReceiver::new(subs::Subs::new(), &broadcast_receiver, &mpsc_sender);
-        let text = WithReceiver::new(text::Text::new(), &broadcast_receiver, &mpsc_sender);
-        let copypastaleader = WithReceiver::new(copypastaleader::CopypastaLeader::new(), &broadcast_receiver, &mpsc_sender);
-        let emote = WithReceiver::new(emote::Emote::new(seventv_client.clone()), &broadcast_receiver, &mpsc_sender);
-        let emote_use = WithReceiver::new(emoteuse::EmoteUse::new(seventv_client), &broadcast_receiver, &mpsc_sender);
-
-        defaults.insert(bits.get_name(), 0.0);
-        defaults.insert(subs.get_name(), 0.0);
-        defaults.insert(text.get_name(), 0.0);
-        defaults.insert(copypastaleader.get_name(), 0.0);
-        defaults.insert(emote.get_name(), 0.0);
-        defaults.insert(emote_use.get_name(), 0.0);
+        let defaults: HashMap<String, f32> = registry
+            .names()
+            .into_iter()
+            .map(|name| (name, 0.0))
+            .collect();
+
+        let metrics = registry
+            .metrics
+            .into_iter()
+            .map(|metric| WithReceiver::new(metric, fan_out, &mpsc_sender))
+            .collect();
 
         Self {
             defaults,
-            bits,
-            subs,
-            text,
-            copypastaleader,
-            emote,
-            emote_use,
+            metrics,
+            workers: default_workers(),
         }
     }
 
+    /// Sets the number of workers each parallelizable metric is sharded across
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
     pub async fn run(&mut self) {
-        join!(
-            calc_metric(&mut self.bits),
-            calc_metric(&mut self.subs),
-            calc_metric(&mut self.text),
-            calc_metric(&mut self.copypastaleader),
-            calc_metric(&mut self.emote),
-            calc_metric(&mut self.emote_use),
-        );
+        let workers = self.workers;
+        join_all(self.metrics.iter_mut().map(|metric| {
+            let forks: Vec<Box<dyn AbstractMetric + Send>> =
+                if workers > 1 && metric.metric.can_parallelize() {
+                    (0..workers).map_while(|_| metric.metric.fork()).collect()
+                } else {
+                    Vec::new()
+                };
+
+            async move {
+                if forks.len() > 1 {
+                    debug!(
+                        "Running metric {} across {} workers",
+                        metric.metric.get_name(),
+                        forks.len()
+                    );
+                    calc_metric_parallel(metric, forks).await
+                } else {
+                    calc_metric(metric).await
+                }
+            }
+        }))
+        .await;
         debug!("All metrics finished");
     }
 }
 
-async fn calc_metric<M: AbstractMetric + Sync + Send + 'static>(
-    metric: &mut WithReceiver<M>
-) {
+async fn calc_metric(metric: &mut WithReceiver) {
     /*
-    Calculate the metric based on chat messages sent by a tokio broadcast channel
+    Calculate the metric based on chat messages sent by the fan-out
     */
-    while let Ok((message, sequence_no)) = metric.receiver.recv().await {
-        let metric_result = (*metric).get_metric(message, sequence_no);
+    while let Some((message, sequence_no)) = metric.receiver.recv().await {
+        let metric_result = metric.get_metric(&message, sequence_no);
         if let Err(e) = metric.sender.send(metric_result).await {
             warn!("Failed to send metric result: {}", e)
         };
@@ -124,18 +223,159 @@ async fn calc_metric<M: AbstractMetric + Sync + Send + 'static>(
     };
 }
 
-#[allow(clippy::type_complexity)]
-/// Get the default values for the metrics and set up the channels
-pub fn setup_metrics_and_channels(
-    seventv_client: Arc<SevenTVClient>,
-) -> (
-    MetricProcessor,
-    broadcast::Sender<(Message, u32)>,
-    mpsc::Receiver<MetricUpdate>,
+async fn calc_metric_parallel(
+    metric: &mut WithReceiver,
+    forks: Vec<Box<dyn AbstractMetric + Send>>,
 ) {
-    let (broadcast_sender, broadcast_receiver) = broadcast::channel(100000);
+    /*
+    Calculate a parallelizable metric by dealing the messages out to
+    workers round-robin, then collecting their results round-robin, so
+    that the results are sent on in the same order as a sequential run
+    */
+    let mut inputs = Vec::with_capacity(forks.len());
+    let mut outputs = Vec::with_capacity(forks.len());
+    let mut workers = Vec::with_capacity(forks.len());
+    for mut fork in forks {
+        let (input_sender, mut input_receiver) =
+            mpsc::channel::<(Arc<Message>, u32)>(WORKER_CAPACITY);
+        let (output_sender, output_receiver) =
+            mpsc::channel::<(MetricUpdate, u32)>(WORKER_CAPACITY);
+        workers.push(tokio::spawn(async move {
+            while let Some((message, sequence_no)) = input_receiver.recv().await {
+                if output_sender
+                    .send((fork.get_metric(&message, sequence_no), sequence_no))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            fork
+        }));
+        inputs.push(input_sender);
+        outputs.push(output_receiver);
+    }
+
+    let receiver = &mut metric.receiver;
+    let dispatch = async move {
+        let mut index = 0;
+        while let Some(item) = receiver.recv().await {
+            if inputs[index % inputs.len()].send(item).await.is_err() {
+                warn!("Metric worker stopped early");
+                break;
+            }
+            index += 1;
+        }
+        // Dropping the inputs lets the workers finish
+    };
+
+    let sender = &metric.sender;
+    let merge = async {
+        let mut index = 0;
+        let worker_count = outputs.len();
+        while let Some(metric_result) = outputs[index % worker_count].recv().await {
+            if let Err(e) = sender.send(metric_result).await {
+                warn!("Failed to send metric result: {}", e)
+            };
+            index += 1;
+        }
+    };
+
+    join!(dispatch, merge);
+
+    for worker in workers {
+        let fork = worker.await.expect("Metric worker panicked");
+        if let Err(e) = metric
+            .sender
+            .send((fork.finish(), FINISH_SEQUENCE_NO))
+            .await
+        {
+            warn!("Failed to send final metric result: {}", e)
+        };
+    }
+}
+
+/// Set up the channels for the metrics in the registry, subscribing
+/// each of them to the fan-out
+pub fn setup_metrics_and_channels(
+    registry: MetricRegistry,
+    fan_out: &mut FanOut<(Arc<Message>, u32)>,
+) -> (MetricProcessor, mpsc::Receiver<(MetricUpdate, u32)>) {
     let (mpsc_sender, mpsc_receiver) = mpsc::channel(100000);
-    let metric_processor =
-        MetricProcessor::new(seventv_client, broadcast_receiver, mpsc_sender);
-    (metric_processor, broadcast_sender, mpsc_receiver)
+    let metric_processor = MetricProcessor::new(registry, fan_out, mpsc_sender);
+    (metric_processor, mpsc_receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use twitch_utils::twitchtypes::Comment;
+
+    use super::*;
+    use crate::scoring::MetricScoring;
+
+    fn cheer(bits: u32) -> Message {
+        let comment: Comment = serde_json::from_value(json!({
+            "_id": "1",
+            "message": {
+                "body": "Cheer",
+                "bits_spent": bits,
+                "fragments": [{ "text": "Cheer", "emoticon": null }],
+                "user_badges": null,
+            },
+            "commenter": { "display_name": "user", "_id": "id", "logo": "" },
+        }))
+        .unwrap();
+        Message::from(comment)
+    }
+
+    fn registry() -> MetricRegistry {
+        MetricRegistry::with_builtins(
+            Arc::new(SevenTVClient::default()),
+            &ScoringConfig::default(),
+        )
+    }
+
+    #[test]
+    fn builtins_are_registered_once_each() {
+        let mut names = registry().names();
+        names.sort();
+
+        let mut builtins = BUILTIN_METRICS.map(String::from).to_vec();
+        builtins.sort();
+        assert_eq!(names, builtins);
+    }
+
+    #[test]
+    fn registering_a_metric_with_a_taken_name_replaces_it() {
+        let mut registry = registry();
+
+        registry.register(Box::new(bits::Bits::new(MetricScoring::linear(5.0))));
+
+        assert_eq!(registry.names().len(), BUILTIN_METRICS.len());
+        let mut bits = registry.unregister("bits").unwrap();
+        let update = bits.get_metric(&cheer(100), 0);
+        assert_eq!(update.updates["id"], 500.0);
+        assert!(!registry.contains("bits"));
+    }
+
+    #[test]
+    fn unregistering_an_unknown_name_does_nothing() {
+        let mut registry = registry();
+
+        assert!(registry.unregister("unknown").is_none());
+        assert_eq!(registry.names().len(), BUILTIN_METRICS.len());
+    }
+
+    #[test]
+    fn only_builtin_names_can_be_constructed() {
+        let client = Arc::new(SevenTVClient::default());
+        let scoring = ScoringConfig::default();
+
+        for name in BUILTIN_METRICS {
+            let metric = builtin_metric(name, client.clone(), &scoring).unwrap();
+            assert_eq!(metric.get_name(), name);
+        }
+        assert!(builtin_metric("unknown", client, &scoring).is_none());
+    }
 }
@@ -4,10 +4,9 @@ use regex::Regex;
 
 use crate::_types::clptypes::{Message, MetricUpdate};
 use crate::metrics::metrictrait::AbstractMetric;
+use crate::scoring::MetricScoring;
 use twitch_utils::twitchtypes::ChatMessageFragment;
 
-const WEIGHT_SUBS: f32 = 0.1;
-
 lazy_static! {
     static ref GIFTED_SUB_REGEX_1: Regex = Regex::new(
         r"(?P<gifter>[a-zA-Z0-9_]+) gifted a Tier (?P<tier>[0-9]) Sub to (?P<receiver>[a-zA-Z0-9_]+)!"
@@ -17,12 +16,14 @@ lazy_static! {
     ).unwrap();
 }
 
-#[derive(Default, Debug)]
-pub struct Subs;
+#[derive(Debug)]
+pub struct Subs {
+    scoring: MetricScoring,
+}
 
 impl Subs {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(scoring: MetricScoring) -> Self {
+        Self { scoring }
     }
 }
 
@@ -31,11 +32,15 @@ impl AbstractMetric for Subs {
         true
     }
 
+    fn fork(&self) -> Option<Box<dyn AbstractMetric + Send>> {
+        Some(Box::new(Self::new(self.scoring.clone())))
+    }
+
     fn get_name(&self) -> String {
         String::from("subs")
     }
 
-    fn get_metric(&mut self, message: Message, _sequence_no: u32) -> MetricUpdate {
+    fn get_metric(&mut self, message: &Message, _sequence_no: u32) -> MetricUpdate {
         match message {
             Message::Twitch(comment) => {
                 let total_subs: i32 = comment
@@ -45,7 +50,7 @@ impl AbstractMetric for Subs {
                     .map(no_of_gifted_subs)
                     .sum();
 
-                let score = total_subs as f32 * WEIGHT_SUBS;
+                let score = self.scoring.score(total_subs as f32);
                 self.twitch_comment_shortcut(comment, score)
             }
             _ => MetricUpdate::empty_with_name(self.get_name()),
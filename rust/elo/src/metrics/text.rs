@@ -3,45 +3,51 @@ use std::collections::HashMap;
 
 use crate::_types::clptypes::{Message, MetricUpdate};
 use crate::metrics::metrictrait::AbstractMetric;
+use crate::scoring::MetricScoring;
 
-const WEIGHT_TEXT: f32 = 0.02;
-
-#[derive(Default, Debug)]
-pub struct Text;
+#[derive(Debug)]
+pub struct Text {
+    scoring: MetricScoring,
+}
 
 impl Text {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(scoring: MetricScoring) -> Self {
+        Self { scoring }
+    }
+
+    /// Scores a message by its length in bytes
+    fn calculate_score(&self, length: usize) -> f32 {
+        f32::max(0.0, self.scoring.score(length as f32))
     }
 }
 
 impl AbstractMetric for Text {
     fn can_parallelize(&self) -> bool {
-        false
+        true
+    }
+
+    fn fork(&self) -> Option<Box<dyn AbstractMetric + Send>> {
+        Some(Box::new(Self::new(self.scoring.clone())))
     }
 
     fn get_name(&self) -> String {
         String::from("text")
     }
 
-    fn get_metric(&mut self, message: Message, _sequence_no: u32) -> MetricUpdate {
+    fn get_metric(&mut self, message: &Message, _sequence_no: u32) -> MetricUpdate {
         match message {
             Message::Twitch(comment) => {
-                let score = f32::max(0.0, calculate_score(comment.message.body.len()));
+                let score = self.calculate_score(comment.message.body.len());
                 self.twitch_comment_shortcut(comment, score)
             }
             Message::Discord(msg) => MetricUpdate {
                 metric_name: self.get_name(),
                 updates: HashMap::from([(
-                    msg.author.id,
-                    f32::max(0.0, calculate_score(msg.content.len())),
+                    msg.author.id.clone(),
+                    self.calculate_score(msg.content.len()),
                 )]),
             },
-            _ => MetricUpdate::default(),
+            _ => MetricUpdate::empty_with_name(self.get_name()),
         }
     }
 }
-
-fn calculate_score(x: usize) -> f32 {
-    -WEIGHT_TEXT * x as f32 * (x as f32 - 20.0)
-}
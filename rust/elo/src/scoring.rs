@@ -0,0 +1,315 @@
+//! Tunable scoring parameters for the metrics and leaderboards, loaded
+//! from a TOML file
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+/// The scoring config that ships with this crate
+pub const DEFAULT_SCORING: &str = include_str!("../scoring.toml");
+
+/// The shape of a metric's scoring function.
+///
+/// A metric scores an input (message length, number of bits, ...) as
+/// `weight * curve(input)`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Curve {
+    /// `x`, or `min(x, cap)` if a cap is set
+    Linear {
+        #[serde(default)]
+        cap: Option<f32>,
+    },
+    /// `x * (zero - x)`, peaking at `zero / 2` and giving nothing from `zero` on
+    Parabola { zero: f32 },
+    /// `ln(1 + x)`
+    Logarithmic,
+    /// Straight lines between `(x, y)` points, flat before the first and
+    /// after the last point
+    Piecewise { points: Vec<(f32, f32)> },
+}
+
+impl Curve {
+    pub fn evaluate(&self, x: f32) -> f32 {
+        match self {
+            Curve::Linear { cap: None } => x,
+            Curve::Linear { cap: Some(cap) } => x.min(*cap),
+            Curve::Parabola { zero } => f32::max(0.0, x * (zero - x)),
+            Curve::Logarithmic => x.max(0.0).ln_1p(),
+            Curve::Piecewise { points } => {
+                let (first, last) = (points[0], points[points.len() - 1]);
+                if x <= first.0 {
+                    return first.1;
+                }
+                if x >= last.0 {
+                    return last.1;
+                }
+                let segment = points
+                    .windows(2)
+                    .find(|segment| x <= segment[1].0)
+                    .expect("x is within the points");
+                let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+                if x1 == x0 {
+                    return y1;
+                }
+                y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+            }
+        }
+    }
+
+    /// `weight * curve(x)`
+    pub fn score(&self, weight: f32, x: f32) -> f32 {
+        match self {
+            /* Same order of operations as the original text score, so the
+            default scoring gives exactly the same results */
+            Curve::Parabola { zero } => f32::max(0.0, -weight * x * (x - zero)),
+            _ => weight * self.evaluate(x),
+        }
+    }
+
+    fn validate(&self) -> Result<(), ScoringConfigError> {
+        match self {
+            Curve::Piecewise { points } => {
+                if points.is_empty() {
+                    return Err(ScoringConfigError(
+                        "a piecewise curve needs at least one point".to_string(),
+                    ));
+                }
+                if points.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+                    return Err(ScoringConfigError(
+                        "the points of a piecewise curve must be in increasing x order".to_string(),
+                    ));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How a metric turns its input into a score
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricScoring {
+    pub weight: f32,
+    pub curve: Curve,
+}
+
+impl MetricScoring {
+    pub fn linear(weight: f32) -> Self {
+        Self {
+            weight,
+            curve: Curve::Linear { cap: None },
+        }
+    }
+
+    pub fn score(&self, x: f32) -> f32 {
+        self.curve.score(self.weight, x)
+    }
+}
+
+/// Scoring for the copypasta metric, which also controls how chains are
+/// detected
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CopypastaScoring {
+    pub weight: f32,
+    pub curve: Curve,
+    /// How many messages back a copypasta can be continued from
+    pub chain_grace: u32,
+    /// How similar a message must be to continue a copypasta, from 0 to 1
+    pub matching_threshold: f32,
+}
+
+impl CopypastaScoring {
+    pub fn score(&self, chain_length: f32) -> f32 {
+        self.curve.score(self.weight, chain_length)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LeaderboardScoring {
+    /// K-factor for leaderboards that don't set their own
+    pub k: f32,
+}
+
+/// Every tunable scoring parameter.
+///
+/// Sections left out of the file keep their values from `scoring.toml`,
+/// but a section that is given must set all of its fields.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScoringConfig {
+    pub bits: MetricScoring,
+    pub subs: MetricScoring,
+    pub text: MetricScoring,
+    pub emote: MetricScoring,
+    pub emote_use: MetricScoring,
+    pub copypasta: CopypastaScoring,
+    pub leaderboards: LeaderboardScoring,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        DEFAULT_SCORING
+            .parse()
+            .expect("Default scoring config is valid")
+    }
+}
+
+impl ScoringConfig {
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_str(&std::fs::read_to_string(path)?)
+    }
+}
+
+impl FromStr for ScoringConfig {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut sections: toml::Table = DEFAULT_SCORING.parse()?;
+        sections.extend(s.parse::<toml::Table>()?);
+        let config: ScoringConfig = toml::Value::Table(sections).try_into()?;
+        for curve in [
+            &config.bits.curve,
+            &config.subs.curve,
+            &config.text.curve,
+            &config.emote.curve,
+            &config.emote_use.curve,
+            &config.copypasta.curve,
+        ] {
+            curve.validate()?;
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug)]
+pub struct ScoringConfigError(String);
+
+impl fmt::Display for ScoringConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid scoring config: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScoringConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_curves_stop_at_their_cap() {
+        let uncapped = Curve::Linear { cap: None };
+        let capped = Curve::Linear { cap: Some(40.0) };
+
+        assert_eq!(uncapped.evaluate(100.0), 100.0);
+        assert_eq!(capped.evaluate(0.0), 0.0);
+        assert_eq!(capped.evaluate(39.0), 39.0);
+        assert_eq!(capped.evaluate(40.0), 40.0);
+        assert_eq!(capped.evaluate(41.0), 40.0);
+        assert_eq!(capped.evaluate(-5.0), -5.0);
+        assert_eq!(capped.score(0.5, 100.0), 20.0);
+    }
+
+    #[test]
+    fn parabolas_peak_halfway_to_their_zero() {
+        let curve = Curve::Parabola { zero: 20.0 };
+
+        assert_eq!(curve.evaluate(0.0), 0.0);
+        assert_eq!(curve.evaluate(10.0), 100.0);
+        assert!(curve.evaluate(9.0) < curve.evaluate(10.0));
+        assert!(curve.evaluate(11.0) < curve.evaluate(10.0));
+        assert_eq!(curve.evaluate(20.0), 0.0);
+        assert_eq!(curve.evaluate(30.0), 0.0);
+        assert_eq!(curve.evaluate(-1.0), 0.0);
+        assert!((curve.score(0.02, 10.0) - 2.0).abs() < 1e-6);
+        assert_eq!(curve.score(0.02, 30.0), 0.0);
+    }
+
+    #[test]
+    fn logarithmic_curves_ignore_negative_input() {
+        let curve = Curve::Logarithmic;
+
+        assert_eq!(curve.evaluate(0.0), 0.0);
+        assert_eq!(curve.evaluate(-3.0), 0.0);
+        assert!((curve.evaluate(std::f32::consts::E - 1.0) - 1.0).abs() < 1e-6);
+        assert!(curve.evaluate(1000.0) < 7.0);
+    }
+
+    #[test]
+    fn piecewise_curves_interpolate_and_are_flat_outside_their_points() {
+        let curve = Curve::Piecewise {
+            points: vec![(0.0, 0.0), (20.0, 100.0), (200.0, 150.0)],
+        };
+
+        assert_eq!(curve.evaluate(-10.0), 0.0);
+        assert_eq!(curve.evaluate(0.0), 0.0);
+        assert_eq!(curve.evaluate(10.0), 50.0);
+        assert_eq!(curve.evaluate(20.0), 100.0);
+        assert_eq!(curve.evaluate(110.0), 125.0);
+        assert_eq!(curve.evaluate(200.0), 150.0);
+        assert_eq!(curve.evaluate(1000.0), 150.0);
+
+        let single = Curve::Piecewise {
+            points: vec![(5.0, 3.0)],
+        };
+        assert_eq!(single.evaluate(0.0), 3.0);
+        assert_eq!(single.evaluate(10.0), 3.0);
+
+        let step = Curve::Piecewise {
+            points: vec![(0.0, 0.0), (10.0, 0.0), (10.0, 5.0), (20.0, 5.0)],
+        };
+        assert_eq!(step.evaluate(10.0), 0.0);
+        assert_eq!(step.evaluate(10.5), 5.0);
+    }
+
+    #[test]
+    fn the_shipped_config_loads() {
+        let config = ScoringConfig::default();
+
+        assert_eq!(config.text.curve, Curve::Parabola { zero: 20.0 });
+        assert_eq!(config.bits, MetricScoring::linear(0.1));
+        assert_eq!(config.copypasta.chain_grace, 10);
+        assert_eq!(config.leaderboards.k, 2.0);
+    }
+
+    #[test]
+    fn sections_left_out_keep_their_defaults() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            "[text]\nweight = 1.0\ncurve = { type = \"linear\", cap = 40 }\n",
+        )
+        .unwrap();
+
+        let config = ScoringConfig::from_file(file.path().to_str().unwrap()).unwrap();
+
+        let defaults = ScoringConfig::default();
+        assert_eq!(
+            config.text,
+            MetricScoring {
+                weight: 1.0,
+                curve: Curve::Linear { cap: Some(40.0) },
+            }
+        );
+        assert_eq!(config.bits, defaults.bits);
+        assert_eq!(config.copypasta, defaults.copypasta);
+        assert_eq!(config.leaderboards, defaults.leaderboards);
+    }
+
+    #[test]
+    fn invalid_configs_are_errors() {
+        for config in [
+            "[text]\nweight = 1.0\n",
+            "[text]\nweight = 1.0\ncurve = { type = \"cubic\" }\n",
+            "[text]\nweight = 1.0\ncurve = { type = \"piecewise\", points = [] }\n",
+            "[text]\nweight = 1.0\ncurve = { type = \"piecewise\", points = [[2, 0], [1, 0]] }\n",
+            "[unknown]\nweight = 1.0\n",
+        ] {
+            assert!(config.parse::<ScoringConfig>().is_err(), "{}", config);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Where the chat processing gets its Twitch and 7TV data from.
+//!
+//! `LiveDataSource` asks Helix and 7TV, `SnapshotDataSource` reads a JSON
+//! snapshot from disk so that the pipeline can run without credentials or
+//! network access. `DataSnapshot::record` makes a snapshot from any source.
+use std::collections::HashMap;
+use std::fs;
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::twitchtypes::SevenTVEmote;
+use crate::{seventvclient, TwitchAPIWrapper};
+
+/// Badge image urls, keyed by badge set id and then by badge version
+pub type BadgeSets = HashMap<String, HashMap<String, String>>;
+
+/// When a VOD started and ended
+pub type VodTimes = (DateTime<FixedOffset>, DateTime<FixedOffset>);
+
+/// The parts of a VOD needed to line the chat up with other sources
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VodInfo {
+    pub id: String,
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+}
+
+#[async_trait]
+pub trait ChatDataSource: Send + Sync {
+    /// Ids of the latest VODs of a channel, oldest first
+    async fn get_latest_vod_ids(&self, ch_id: String, num: usize) -> Vec<String>;
+
+    /// Returns a tuple (start timestamp and end timestamp) of the VOD
+    async fn get_vod_times(&self, vod_id: String) -> Result<VodTimes, Box<dyn std::error::Error>>;
+
+    /// The VOD's id with its start and end times
+    async fn get_vod_info(&self, vod_id: String) -> Result<VodInfo, Box<dyn std::error::Error>> {
+        let (start, end) = self.get_vod_times(vod_id.clone()).await?;
+        Ok(VodInfo {
+            id: vod_id,
+            start,
+            end,
+        })
+    }
+
+    /// The global and channel chat badges
+    async fn get_badges(&self, ch_id: String) -> Result<BadgeSets, Box<dyn std::error::Error>>;
+
+    /// The 7TV emotes of the channel
+    async fn get_seventv_emotes(&self) -> Vec<SevenTVEmote>;
+}
+
+/// Gets everything from the Twitch and 7TV APIs
+pub struct LiveDataSource {
+    twitch: TwitchAPIWrapper,
+}
+
+impl LiveDataSource {
+    pub fn new(twitch: TwitchAPIWrapper) -> Self {
+        Self { twitch }
+    }
+}
+
+#[async_trait]
+impl ChatDataSource for LiveDataSource {
+    async fn get_latest_vod_ids(&self, ch_id: String, num: usize) -> Vec<String> {
+        self.twitch.get_latest_vod_ids(ch_id, num).await
+    }
+
+    async fn get_vod_times(&self, vod_id: String) -> Result<VodTimes, Box<dyn std::error::Error>> {
+        self.twitch.get_vod_times(vod_id).await
+    }
+
+    async fn get_badges(&self, ch_id: String) -> Result<BadgeSets, Box<dyn std::error::Error>> {
+        let badge_sets = self
+            .twitch
+            .get_badges(ch_id)
+            .await?
+            .into_iter()
+            .map(|(set_id, badges)| {
+                (
+                    set_id,
+                    badges
+                        .into_iter()
+                        .map(|(badge_id, badge)| (badge_id, badge.image_url_4x))
+                        .collect(),
+                )
+            })
+            .collect();
+        Ok(badge_sets)
+    }
+
+    async fn get_seventv_emotes(&self) -> Vec<SevenTVEmote> {
+        seventvclient::fetch_emotes().await
+    }
+}
+
+/// Everything a `ChatDataSource` provides, as saved to disk
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DataSnapshot {
+    /// Badges, keyed by channel id
+    #[serde(default)]
+    pub badges: HashMap<String, BadgeSets>,
+    /// VODs of each channel, keyed by channel id, newest first
+    #[serde(default)]
+    pub vods: HashMap<String, Vec<VodInfo>>,
+    #[serde(default)]
+    pub seventv_emotes: Vec<SevenTVEmote>,
+}
+
+impl DataSnapshot {
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let snapshot = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&snapshot)?)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        info!("Data snapshot saved to {}", path);
+        Ok(())
+    }
+
+    /// Takes a snapshot of the data a source has for a channel and its
+    /// latest `num_vods` VODs
+    pub async fn record(
+        source: &dyn ChatDataSource,
+        ch_id: String,
+        num_vods: usize,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let badges = source.get_badges(ch_id.clone()).await?;
+
+        let mut vods = Vec::new();
+        for id in source.get_latest_vod_ids(ch_id.clone(), num_vods).await {
+            let (start, end) = source.get_vod_times(id.clone()).await?;
+            vods.push(VodInfo { id, start, end });
+        }
+        vods.reverse();
+
+        Ok(Self {
+            badges: HashMap::from([(ch_id.clone(), badges)]),
+            vods: HashMap::from([(ch_id, vods)]),
+            seventv_emotes: source.get_seventv_emotes().await,
+        })
+    }
+}
+
+/// Serves everything from a `DataSnapshot`, without any network access
+pub struct SnapshotDataSource {
+    snapshot: DataSnapshot,
+}
+
+impl SnapshotDataSource {
+    pub fn new(snapshot: DataSnapshot) -> Self {
+        Self { snapshot }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        info!("Loading data snapshot from {}", path);
+        Ok(Self::new(DataSnapshot::from_file(path)?))
+    }
+}
+
+#[async_trait]
+impl ChatDataSource for SnapshotDataSource {
+    async fn get_latest_vod_ids(&self, ch_id: String, num: usize) -> Vec<String> {
+        self.snapshot
+            .vods
+            .get(&ch_id)
+            .map(|vods| {
+                vods.iter()
+                    .take(num)
+                    .map(|vod| vod.id.clone())
+                    .rev()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    async fn get_vod_times(&self, vod_id: String) -> Result<VodTimes, Box<dyn std::error::Error>> {
+        let vod = self
+            .snapshot
+            .vods
+            .values()
+            .flatten()
+            .find(|vod| vod.id == vod_id)
+            .ok_or_else(|| format!("VOD {} isn't in the data snapshot", vod_id))?;
+        Ok((vod.start, vod.end))
+    }
+
+    async fn get_badges(&self, ch_id: String) -> Result<BadgeSets, Box<dyn std::error::Error>> {
+        match self.snapshot.badges.get(&ch_id) {
+            Some(badges) => Ok(badges.clone()),
+            None => {
+                warn!("No badges for channel {} in the data snapshot", ch_id);
+                Ok(BadgeSets::new())
+            }
+        }
+    }
+
+    async fn get_seventv_emotes(&self) -> Vec<SevenTVEmote> {
+        self.snapshot.seventv_emotes.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn an_unknown_vod_is_an_error() {
+        let start = DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z").unwrap();
+        let source = SnapshotDataSource::new(DataSnapshot {
+            vods: HashMap::from([(
+                "85498365".to_string(),
+                vec![VodInfo {
+                    id: "1".to_string(),
+                    start,
+                    end: start,
+                }],
+            )]),
+            ..Default::default()
+        });
+
+        assert!(source.get_vod_times("1".to_string()).await.is_ok());
+        assert!(source.get_vod_times("2".to_string()).await.is_err());
+        assert!(source.get_vod_info("2".to_string()).await.is_err());
+    }
+}
@@ -1,12 +1,14 @@
 use std::collections::HashMap;
+use std::error::Error;
 
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, TimeDelta};
 use log::debug;
 use twitch_api::helix::chat::{ChatBadge, GetChannelChatBadgesRequest, GetGlobalChatBadgesRequest};
-use twitch_api::helix::videos::GetVideosRequest;
+use twitch_api::helix::videos::{GetVideosRequest, Video};
 use twitch_api::twitch_oauth2::{AppAccessToken, ClientId, ClientSecret};
 use twitch_api::HelixClient;
 
+pub mod datasource;
 pub mod seventvclient;
 pub mod seventvtypes;
 pub mod twitchtypes;
@@ -40,6 +42,25 @@ fn parse_time(duration_str: &str) -> Result<u32, Box<dyn std::error::Error>> {
     Ok(hours * 3600 + minutes * 60 + seconds)
 }
 
+/// The start and end of the VOD Helix returned for `vod_id`
+fn vod_times(
+    vod_id: &str,
+    videos: &[Video],
+) -> Result<(DateTime<FixedOffset>, DateTime<FixedOffset>), Box<dyn Error>> {
+    let video = videos
+        .first()
+        .ok_or_else(|| format!("Twitch has no VOD {}", vod_id))?;
+
+    let start_timestamp = DateTime::parse_from_rfc3339(video.created_at.as_str())?;
+    let duration = TimeDelta::new(parse_time(video.duration.as_str())? as i64, 0)
+        .ok_or_else(|| format!("VOD {} has an invalid duration", vod_id))?;
+    let end_timestamp = start_timestamp
+        .checked_add_signed(duration)
+        .ok_or_else(|| format!("VOD {} ends out of range", vod_id))?;
+
+    Ok((start_timestamp, end_timestamp))
+}
+
 #[derive(Clone)]
 pub struct TwitchAPIWrapper {
     pub twitch: HelixClient<'static, reqwest::Client>,
@@ -73,48 +94,39 @@ impl TwitchAPIWrapper {
         Ok(Self { twitch, token })
     }
 
+    /// Ids of the latest `num` VODs of a channel, oldest first
     pub async fn get_latest_vod_ids(&self, ch_id: String, num: usize) -> Vec<String> {
-        let request = GetVideosRequest::user_id(ch_id.clone());
-        let response = self.twitch.req_get(request, &self.token);
-        response
-            .await
-            .unwrap()
-            .data
-            .iter()
-            .take(num)
-            .map(|v| v.id.clone().to_string())
-            .rev()
-            .collect()
+        let mut request = GetVideosRequest::user_id(ch_id.clone());
+        /* Twitch returns 20 videos unless asked for up to 100, and the rest
+        one page at a time */
+        request.first = Some(num.clamp(1, 100));
+        let mut vod_ids: Vec<String> = Vec::new();
+        let mut page = Some(self.twitch.req_get(request, &self.token).await.unwrap());
+        while let Some(response) = page {
+            vod_ids.extend(response.data.iter().map(|v| v.id.to_string()));
+            if vod_ids.len() >= num {
+                break;
+            }
+            page = response.get_next(&self.twitch, &self.token).await.unwrap();
+        }
+        vod_ids.truncate(num);
+        vod_ids.reverse();
+        vod_ids
     }
 
-    /// Returns a tuple (start timestamp and end timestamp) of the VOD
+    /// Returns a tuple (start timestamp and end timestamp) of the VOD, or
+    /// an error if Twitch doesn't know it, e.g. because it was deleted
     pub async fn get_vod_times(
         &self,
         vod_id: String,
-    ) -> (DateTime<FixedOffset>, DateTime<FixedOffset>) {
+    ) -> Result<(DateTime<FixedOffset>, DateTime<FixedOffset>), Box<dyn Error>> {
         let vod_ids = [(&vod_id).into()];
         let vod_info = self
             .twitch
             .req_get(GetVideosRequest::ids(&vod_ids), &self.token)
-            .await
-            .unwrap();
-
-        let start_timestamp =
-            chrono::DateTime::parse_from_rfc3339(vod_info.data[0].created_at.as_str())
-                .expect("can interpret datetime");
-
-        let end_timestamp = start_timestamp
-            .checked_add_signed(
-                chrono::TimeDelta::new(
-                    parse_time(vod_info.data[0].duration.as_str()).expect("parsable duration")
-                        as i64,
-                    0,
-                )
-                .expect("can convert to timedelta"),
-            )
-            .expect("can get end duration");
-
-        (start_timestamp, end_timestamp)
+            .await?;
+
+        vod_times(&vod_id, &vod_info.data)
     }
 
     pub async fn get_badges(
@@ -146,3 +158,45 @@ impl TwitchAPIWrapper {
         Ok(badge_sets)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(created_at: &str, duration: &str) -> Video {
+        serde_json::from_value(serde_json::json!({
+            "id": "2000000000",
+            "stream_id": null,
+            "user_id": "85498365",
+            "user_login": "vedal987",
+            "user_name": "vedal987",
+            "title": "",
+            "description": "",
+            "created_at": created_at,
+            "published_at": created_at,
+            "url": "",
+            "thumbnail_url": "",
+            "viewable": "public",
+            "view_count": 0,
+            "language": "en",
+            "type": "archive",
+            "duration": duration,
+            "muted_segments": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn a_vod_ends_its_duration_after_it_starts() {
+        let (start, end) =
+            vod_times("2000000000", &[video("2024-01-02T03:04:05Z", "1h2m3s")]).unwrap();
+
+        assert_eq!(start.to_rfc3339(), "2024-01-02T03:04:05+00:00");
+        assert_eq!(end.to_rfc3339(), "2024-01-02T04:06:08+00:00");
+    }
+
+    #[test]
+    fn an_unknown_vod_is_an_error() {
+        assert!(vod_times("2000000000", &[]).is_err());
+    }
+}
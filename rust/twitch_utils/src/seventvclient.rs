@@ -1,5 +1,6 @@
-use std::collections::HashSet;
+use crate::datasource::ChatDataSource;
 use crate::seventvtypes::SevenTVResponse;
+use std::collections::HashSet;
 
 use log::{debug, info};
 
@@ -13,25 +14,35 @@ pub struct SevenTVClient {
     seventv_lookup: HashSet<String>,
 }
 
+/// Gets the 7TV channel emotes, or none if 7TV can't be reached
+pub async fn fetch_emotes() -> Vec<SevenTVEmote> {
+    info!("Getting the 7TV channel emotes");
+    let response = reqwest::get(SEVEN_TV_URL).await;
+    if response.is_err() {
+        info!("Cannot get 7tv emotes");
+        return Vec::new();
+    }
+
+    let response: SevenTVResponse = response.unwrap().json::<SevenTVResponse>().await.unwrap();
+    response
+        .emote_set
+        .emotes
+        .iter()
+        .map(|emote| SevenTVEmote::from(emote.data.clone()))
+        .collect()
+}
+
 impl SevenTVClient {
     pub async fn new() -> Self {
-        info!("Getting the 7TV channel emotes");
-        let response = reqwest::get(SEVEN_TV_URL).await;
-        if response.is_err() {
-            info!("Cannot get 7tv emotes");
-            return Self {
-                seventv_emotes: Vec::new(),
-                seventv_lookup: HashSet::new(),
-            };
-        }
+        Self::from_emotes(fetch_emotes().await)
+    }
 
-        let response: SevenTVResponse = response.unwrap().json::<SevenTVResponse>().await.unwrap();
-        let seventv_emotes: Vec<SevenTVEmote> = response.emote_set
-            .emotes
-            .iter()
-            .map(|emote| SevenTVEmote::from(emote.data.clone()))
-            .collect();
+    /// Creates a client with the emotes from a data source
+    pub async fn from_source(source: &dyn ChatDataSource) -> Self {
+        Self::from_emotes(source.get_seventv_emotes().await)
+    }
 
+    pub fn from_emotes(seventv_emotes: Vec<SevenTVEmote>) -> Self {
         let seventv_lookup: HashSet<String> = seventv_emotes
             .iter()
             .map(|emote| emote.name.clone())
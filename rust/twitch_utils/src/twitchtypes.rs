@@ -2,9 +2,10 @@
 Contains all the Twitch types parsable from the chat log
 */
 
+use crate::seventvtypes::RawSevenTVEmote;
+use chrono::{DateTime, FixedOffset, TimeDelta};
 use serde::{Deserialize, Serialize};
 use twitch_api::helix::chat::{ChannelEmote, GlobalEmote};
-use crate::seventvtypes::RawSevenTVEmote;
 
 const TWITCH_EMOTE_URL: &str = "https://static-cdn.jtvnw.net/emoticons/v2";
 const TWITCH_EMOTE_URL_ENDING: &str = "default/light/1.0";
@@ -84,7 +85,7 @@ impl From<RawSevenTVEmote> for SevenTVEmote {
     }
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct SevenTVEmote {
     pub id: String,
     pub name: String,
@@ -144,14 +145,43 @@ pub struct Comment {
     Represents a comment in a chat.
     */
     pub _id: String,
+    /// Seconds into the VOD the comment was sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_offset_seconds: Option<f64>,
     pub message: ChatMessage,
     pub commenter: ChatUserInfo,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ChatLogVideo {
+    /*
+    The VOD a chat log was downloaded from.
+    */
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<FixedOffset>>,
+    /// Length of the VOD in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<f64>,
+}
+
+impl ChatLogVideo {
+    /// When the VOD started and ended, if TwitchDownloader saved them
+    ///
+    /// Like the Twitch API, the length is counted in whole seconds.
+    pub fn times(&self) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+        let start = self.created_at?;
+        let end = start.checked_add_signed(TimeDelta::new(self.length? as i64, 0)?)?;
+        Some((start, end))
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ChatLog {
     /*
     Represents a chat log.
     */
     pub comments: Vec<Comment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video: Option<ChatLogVideo>,
 }
\ No newline at end of file
@@ -4,5 +4,19 @@
 
 CHATDOWNLOADER="rust"
 WEB="web/static"
+SEASON=$(cat season.txt)
 
-cp ${CHATDOWNLOADER}/*.bin ${WEB}/
+cp ${CHATDOWNLOADER}/seasons/${SEASON}/*.bin ${WEB}/
+
+# Other export formats, if any leaderboard has them. Quarantined users and
+# score explanations are JSON too, but are only for moderators
+shopt -s nullglob
+for export in ${CHATDOWNLOADER}/seasons/${SEASON}/*.{json,csv}; do
+    case "${export}" in
+        *.quarantine.json | *.explain.json) continue ;;
+    esac
+    cp "${export}" ${WEB}/
+done
+if [ -d ${CHATDOWNLOADER}/seasons/${SEASON}/pages ]; then
+    cp -r ${CHATDOWNLOADER}/seasons/${SEASON}/pages ${WEB}/
+fi